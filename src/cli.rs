@@ -1,31 +1,67 @@
-use clap::{Parser, Subcommand};
-use std::path::PathBuf;
+use crate::linux::cgroup::{self, CPU_MIN_QUOTA_US};
+use crate::linux::mounts::propagation_flags;
+use crate::linux::process::rlimit_resource;
+use crate::runtime::spec::{HookStage, Mount, NetworkMode, Rlimit, Tmpfs};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use nix::sys::signal::Signal;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
 
 #[derive(Parser)]
 pub struct Cli {
+    /// Directory for container state (default ~/.podrun)
+    #[arg(long, global = true)]
+    pub root: Option<PathBuf>,
+    /// Also write errors to this file
+    #[arg(long, global = true)]
+    pub log: Option<PathBuf>,
+    #[arg(long, global = true, value_enum, default_value_t = LogFormat::Text)]
+    pub log_format: LogFormat,
     #[command(subcommand)]
     pub cmd: Cmd,
 }
 
+#[derive(Clone, Copy, ValueEnum)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+// parsed once per run, so Create's size doesn't matter
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand)]
 pub enum Cmd {
-    Create {
-        id: String,
-        #[arg(long)]
-        rootfs: PathBuf,
-        #[arg(last = true, required = true)]
-        argv: Vec<String>,
+    Create(CreateArgs),
+    /// Create, start and wait for a container, then delete it; exits with the
+    /// container's exit code
+    Run {
+        #[command(flatten)]
+        create: CreateArgs,
+        /// Record every syscall the container makes instead of filtering, and
+        /// write them to FILE as an allow-list for --seccomp-profile
+        #[arg(long, value_name = "FILE", conflicts_with = "seccomp_profile")]
+        seccomp_learn: Option<PathBuf>,
     },
     Start {
         id: String,
     },
     Kill {
         id: String,
-        #[arg(value_name = "SIGNAL", default_value_t = 15)]
+        /// Signal name (TERM, SIGKILL) or number
+        #[arg(value_name = "SIGNAL", default_value = "TERM", value_parser = parse_signal)]
         signal: i32,
     },
     Delete {
         id: String,
+        /// Kill the container first if it is still running
+        #[arg(long, short)]
+        force: bool,
+    },
+    Pause {
+        id: String,
+    },
+    Resume {
+        id: String,
     },
     Wait {
         id: String,
@@ -37,13 +73,427 @@ pub enum Cmd {
         env: Vec<String>,
         #[arg(long)]
         cwd: Option<PathBuf>,
+        /// Add a capability for this process only. Repeatable.
+        #[arg(long = "cap-add", value_name = "CAP")]
+        cap_add: Vec<String>,
+        /// Drop a capability for this process only. Repeatable.
+        #[arg(long = "cap-drop", value_name = "CAP")]
+        cap_drop: Vec<String>,
+        /// Resource limit for this process, replacing the container's. Repeatable.
+        #[arg(long = "ulimit", value_name = "NAME=SOFT[:HARD]", value_parser = parse_ulimit)]
+        ulimits: Vec<Rlimit>,
+        /// Set no_new_privs for this process
+        #[arg(long)]
+        no_new_privileges: bool,
+        /// OOM score adjustment for this process, -1000 to 1000
+        #[arg(long, allow_negative_numbers = true, value_parser = parse_oom_score_adj)]
+        oom_score_adj: Option<i32>,
         #[arg(last = true, required = true)]
         argv: Vec<String>,
     },
+    /// Show status, pid and timestamps; containers created from a bundle
+    /// print the OCI runtime state as JSON instead, like runc
     State {
         id: String,
+        /// Print podrun's full state as JSON
         #[arg(long)]
         json: bool,
     },
     List,
+    /// Manage named volumes, kept in the store until removed
+    Volume {
+        #[command(subcommand)]
+        cmd: VolumeCmd,
+    },
+    Stats {
+        /// Containers to report on; all running containers if omitted
+        ids: Vec<String>,
+        /// Print a single sample and exit
+        #[arg(long)]
+        no_stream: bool,
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+/// Everything `create` takes; `run` takes the same.
+#[derive(Args)]
+pub struct CreateArgs {
+    pub id: String,
+    #[arg(long, required_unless_present = "bundle")]
+    pub rootfs: Option<PathBuf>,
+    /// OCI bundle directory containing config.json. Conflicts with --rootfs,
+    /// --network, --memory, --cpus, --pids-limit and a command; the other
+    /// options (--mount, --tmpfs, --device, --cap-add, --ulimit, --hook, ...)
+    /// are applied on top of config.json. Like runc, create, start and
+    /// delete print nothing for it.
+    #[arg(long, conflicts_with_all = ["rootfs", "network", "memory", "cpus", "pids_limit", "argv"])]
+    pub bundle: Option<PathBuf>,
+    /// File to write the host pid of init to
+    #[arg(long)]
+    pub pid_file: Option<PathBuf>,
+    /// Unix socket to send the pty master to (needs process.terminal)
+    #[arg(long)]
+    pub console_socket: Option<PathBuf>,
+    /// Copy-on-write rootfs: changes go to an overlay layer that delete
+    /// discards, so one rootfs can back many containers
+    #[arg(long)]
+    pub overlay: bool,
+    /// Mount the container's root filesystem read-only
+    #[arg(long)]
+    pub read_only: bool,
+    /// With --read-only or a bundle's read-only root, keep /tmp and /run
+    /// writable with a tmpfs on each (unless something else is mounted there)
+    #[arg(long)]
+    pub read_only_tmpfs: bool,
+    /// Mount into the container, e.g.
+    /// type=bind,src=/srv/cache,dst=/cache,ro,propagation=rslave or
+    /// type=volume,src=cache,dst=/cache (created on first use). Repeatable.
+    #[arg(long = "mount", value_name = "SPEC", value_parser = parse_mount)]
+    pub mounts: Vec<Mount>,
+    /// Bind mount, short for --mount: /host/path:/container/path[:ro]. Repeatable.
+    #[arg(short = 'v', long = "volume", value_name = "SRC:DST[:ro]", value_parser = parse_volume)]
+    pub volumes: Vec<Mount>,
+    /// tmpfs mount, e.g. /tmp:size=64m,mode=1777,noexec (nosuid and nodev
+    /// unless suid or dev is given; on /dev, nosuid and mode=755 like the
+    /// built-in one, device nodes included). Repeatable.
+    #[arg(long = "tmpfs", value_name = "DST[:OPTS]", value_parser = parse_tmpfs)]
+    pub tmpfs: Vec<Tmpfs>,
+    /// Pass a host device in: HOST[:CONTAINER[:PERMS]], e.g.
+    /// /dev/fuse:/dev/fuse:rwm (permissions r, w, m; default rwm). Repeatable.
+    #[arg(long = "device", value_name = "HOST[:CONTAINER[:PERMS]]", value_parser = parse_device)]
+    pub devices: Vec<(PathBuf, PathBuf, String)>,
+    /// Add a capability to the default set (or the bundle's), e.g.
+    /// NET_ADMIN; ALL for every one. Repeatable.
+    #[arg(long = "cap-add", value_name = "CAP")]
+    pub cap_add: Vec<String>,
+    /// Drop a capability, e.g. NET_RAW; ALL for every one. Repeatable.
+    #[arg(long = "cap-drop", value_name = "CAP")]
+    pub cap_drop: Vec<String>,
+    /// Seccomp profile in Docker/OCI JSON, replacing the built-in one (or
+    /// the bundle's); "unconfined" runs without a filter
+    #[arg(long, value_name = "FILE")]
+    pub seccomp_profile: Option<PathBuf>,
+    /// Set no_new_privs: setuid binaries and file capabilities no longer
+    /// raise privileges
+    #[arg(long)]
+    pub no_new_privileges: bool,
+    /// Resource limit, e.g. nofile=1024:4096 or core=0 (soft[:hard],
+    /// unlimited or -1 for none). Repeatable.
+    #[arg(long = "ulimit", value_name = "NAME=SOFT[:HARD]", value_parser = parse_ulimit)]
+    pub ulimits: Vec<Rlimit>,
+    /// How willing the OOM killer is to pick the container, -1000 (never)
+    /// to 1000
+    #[arg(long, allow_negative_numbers = true, value_parser = parse_oom_score_adj)]
+    pub oom_score_adj: Option<i32>,
+    /// Run a host program at a lifecycle stage, e.g. prestart=/usr/bin/audit
+    /// (stages: prestart, createRuntime, poststart, poststop). Repeatable.
+    #[arg(long = "hook", value_parser = parse_hook)]
+    pub hooks: Vec<(HookStage, PathBuf)>,
+    #[arg(long, value_enum, default_value_t = NetworkMode::None)]
+    pub network: NetworkMode,
+    /// Memory limit, e.g. 512m or 2g
+    #[arg(long, value_parser = parse_bytes)]
+    pub memory: Option<u64>,
+    /// CPU limit as a number of CPUs, e.g. 0.5
+    #[arg(long, value_parser = parse_cpus)]
+    pub cpus: Option<f64>,
+    /// Maximum number of processes
+    #[arg(long)]
+    pub pids_limit: Option<u64>,
+    #[arg(last = true, required_unless_present = "bundle")]
+    pub argv: Vec<String>,
+}
+
+#[derive(Subcommand)]
+pub enum VolumeCmd {
+    Create {
+        name: String,
+    },
+    Ls,
+    /// Print a volume's details as JSON
+    Inspect {
+        #[arg(required = true)]
+        names: Vec<String>,
+    },
+    /// Remove volumes; fails for a volume a container still uses
+    Rm {
+        #[arg(required = true)]
+        names: Vec<String>,
+    },
+    /// Remove every volume no container uses
+    Prune,
+}
+
+/// Parses sizes like `1048576`, `512k`, `64m` or `2g` (powers of 1024).
+fn parse_bytes(s: &str) -> Result<u64, String> {
+    let lower = s.trim().to_ascii_lowercase();
+    let digits = lower.trim_end_matches('b');
+    let (num, mult) = match digits.char_indices().last() {
+        Some((i, 'k')) => (&digits[..i], 1u64 << 10),
+        Some((i, 'm')) => (&digits[..i], 1u64 << 20),
+        Some((i, 'g')) => (&digits[..i], 1u64 << 30),
+        _ => (digits, 1),
+    };
+
+    num.parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(mult))
+        .ok_or_else(|| format!("invalid size '{s}', expected e.g. 512m or 2g"))
+}
+
+fn parse_cpus(s: &str) -> Result<f64, String> {
+    let cpus = s
+        .parse::<f64>()
+        .ok()
+        .filter(|n| n.is_finite() && *n > 0.0)
+        .ok_or_else(|| format!("invalid cpus '{s}', expected a number above 0, e.g. 0.5"))?;
+    if cgroup::cpu_quota(cpus) < CPU_MIN_QUOTA_US {
+        return Err(format!(
+            "cpus '{s}' is below the kernel's minimum CPU quota, use at least 0.01"
+        ));
+    }
+    Ok(cpus)
+}
+
+/// Parses `KILL`, `SIGKILL` or `9`.
+fn parse_signal(s: &str) -> Result<i32, String> {
+    if let Ok(n) = s.parse::<i32>() {
+        return Ok(n);
+    }
+
+    let upper = s.to_ascii_uppercase();
+    let name = if upper.starts_with("SIG") {
+        upper
+    } else {
+        format!("SIG{upper}")
+    };
+    name.parse::<Signal>()
+        .map(|sig| sig as i32)
+        .map_err(|_| format!("unknown signal '{s}'"))
+}
+
+/// Parses `--ulimit name=soft[:hard]`, e.g. `nofile=1024:4096`.
+fn parse_ulimit(s: &str) -> Result<Rlimit, String> {
+    let (name, limits) = s
+        .split_once('=')
+        .ok_or_else(|| format!("invalid ulimit '{s}', expected name=soft[:hard]"))?;
+    let kind = format!("RLIMIT_{}", name.to_ascii_uppercase());
+    rlimit_resource(&kind).map_err(|_| format!("unknown ulimit '{name}'"))?;
+
+    let limit = |v: &str| match v {
+        "unlimited" | "-1" => Ok(libc::RLIM_INFINITY),
+        _ => v
+            .parse::<u64>()
+            .map_err(|_| format!("invalid ulimit value '{v}' in '{s}'")),
+    };
+    let (soft, hard) = limits.split_once(':').unwrap_or((limits, limits));
+    let (soft, hard) = (limit(soft)?, limit(hard)?);
+    if soft > hard {
+        return Err(format!(
+            "ulimit '{s}' has its soft limit above the hard one"
+        ));
+    }
+    Ok(Rlimit { kind, soft, hard })
+}
+
+fn parse_oom_score_adj(s: &str) -> Result<i32, String> {
+    s.parse::<i32>()
+        .ok()
+        .filter(|adj| (-1000..=1000).contains(adj))
+        .ok_or_else(|| format!("invalid oom score adjustment '{s}', expected -1000 to 1000"))
+}
+
+/// Parses `stage=path` for `--hook`.
+fn parse_hook(s: &str) -> Result<(HookStage, PathBuf), String> {
+    let (stage, path) = s
+        .split_once('=')
+        .ok_or_else(|| format!("invalid hook '{s}', expected stage=path"))?;
+    let path = PathBuf::from(path);
+    if !path.is_absolute() {
+        return Err(format!(
+            "hook path must be absolute, got '{}'",
+            path.display()
+        ));
+    }
+    Ok((stage.parse()?, path))
+}
+
+/// Parses `--mount` key=value lists:
+/// `type=bind,src=/host,dst=/ctr[,ro][,propagation=rslave]` or
+/// `type=volume,src=name,dst=/ctr[,ro]`.
+fn parse_mount(s: &str) -> Result<Mount, String> {
+    let mut kind = None;
+    let mut source = None;
+    let mut destination = None;
+    let mut options = vec![];
+
+    for field in s.split(',') {
+        let (key, value) = match field.split_once('=') {
+            Some((k, v)) => (k, Some(v)),
+            None => (field, None),
+        };
+        match (key, value) {
+            ("type", Some(v)) => kind = Some(v),
+            ("src" | "source", Some(v)) => source = Some(PathBuf::from(v)),
+            ("dst" | "destination" | "target", Some(v)) => destination = Some(PathBuf::from(v)),
+            ("ro" | "readonly", None | Some("true" | "1")) => options.push("ro".into()),
+            ("ro" | "readonly", Some("false" | "0")) | ("rw", None) => options.push("rw".into()),
+            ("propagation" | "bind-propagation", Some(v)) => {
+                if propagation_flags(v).is_none() {
+                    return Err(format!("unknown mount propagation '{v}'"));
+                }
+                options.push(v.to_string());
+            }
+            _ => return Err(format!("unknown mount option '{field}' in '{s}'")),
+        }
+    }
+
+    let source = source.ok_or_else(|| format!("mount '{s}' needs src="))?;
+    let destination = destination.ok_or_else(|| format!("mount '{s}' needs dst="))?;
+    match kind {
+        Some("bind") => bind_mount(source, destination, options),
+        // resolved to a bind mount of the volume's directory by create
+        Some("volume") => {
+            check_destination(&destination)?;
+            Ok(Mount {
+                destination,
+                kind: "volume".into(),
+                source: Some(source),
+                options,
+            })
+        }
+        Some(other) => Err(format!("unsupported mount type '{other}'")),
+        None => Err(format!("mount '{s}' needs a type, e.g. type=bind")),
+    }
+}
+
+/// Parses `-v src:dst[:ro]`.
+fn parse_volume(s: &str) -> Result<Mount, String> {
+    let mut options = vec![];
+    let (source, destination) = match s.split(':').collect::<Vec<_>>()[..] {
+        [src, dst] => (src, dst),
+        [src, dst, mode @ ("ro" | "rw")] => {
+            options.push(mode.to_string());
+            (src, dst)
+        }
+        _ => return Err(format!("invalid volume '{s}', expected src:dst[:ro]")),
+    };
+    bind_mount(source.into(), destination.into(), options)
+}
+
+/// Parses `--tmpfs dst[:opt,opt=value,...]`.
+fn parse_tmpfs(s: &str) -> Result<Tmpfs, String> {
+    let (dst, opts) = s.split_once(':').unwrap_or((s, ""));
+    let destination = PathBuf::from(dst);
+    check_destination(&destination)?;
+
+    // one on /dev replaces the built-in one, device nodes and all
+    let defaults = if destination == Path::new("/dev") {
+        ["nosuid", "mode=755"]
+    } else {
+        ["nosuid", "nodev"]
+    };
+    let mut options: Vec<String> = defaults.iter().map(|o| o.to_string()).collect();
+    for opt in opts.split(',').filter(|o| !o.is_empty()) {
+        // a fresh tmpfs has nothing to bind and stays private like the rest
+        if matches!(opt, "bind" | "rbind") || propagation_flags(opt).is_some() {
+            return Err(format!("'{opt}' is not a tmpfs option"));
+        }
+        match opt.split_once('=') {
+            // same units as --memory; tmpfs's own percentages pass through
+            Some(("size", v)) if !v.ends_with('%') => {
+                options.push(format!("size={}", parse_bytes(v)?));
+            }
+            Some(("mode", v)) if u32::from_str_radix(v, 8).is_err() => {
+                return Err(format!("tmpfs mode must be octal, got '{v}'"));
+            }
+            _ => options.push(opt.to_string()),
+        }
+    }
+    Ok(Tmpfs {
+        destination,
+        options,
+    })
+}
+
+/// Parses `--device host[:container[:perms]]`.
+fn parse_device(s: &str) -> Result<(PathBuf, PathBuf, String), String> {
+    let (host, dest, access) = match s.split(':').collect::<Vec<_>>()[..] {
+        [host] => (host, host, "rwm"),
+        [host, dest] => (host, dest, "rwm"),
+        [host, dest, access] => (host, dest, access),
+        _ => {
+            return Err(format!(
+                "invalid device '{s}', expected host[:container[:perms]]"
+            ));
+        }
+    };
+
+    let valid = !access.is_empty()
+        && access.chars().all(|c| matches!(c, 'r' | 'w' | 'm'))
+        && access.chars().count() == access.chars().collect::<HashSet<_>>().len();
+    if !valid {
+        return Err(format!(
+            "invalid device permissions '{access}', expected some of r, w and m"
+        ));
+    }
+
+    let dest = PathBuf::from(dest);
+    check_destination(&dest)?;
+    Ok((host.into(), dest, access.to_string()))
+}
+
+fn bind_mount(
+    source: PathBuf,
+    destination: PathBuf,
+    mut options: Vec<String>,
+) -> Result<Mount, String> {
+    if source.as_os_str().is_empty() {
+        return Err("bind mount source is empty".into());
+    }
+    check_destination(&destination)?;
+    options.insert(0, "rbind".into());
+    Ok(Mount {
+        destination,
+        kind: "bind".into(),
+        source: Some(source),
+        options,
+    })
+}
+
+fn check_destination(destination: &Path) -> Result<(), String> {
+    if !destination.is_absolute() {
+        return Err(format!(
+            "mount destination must be absolute, got '{}'",
+            destination.display()
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cpus_below_the_minimum_quota_are_rejected() {
+        assert!(parse_cpus("0.01").is_ok());
+        assert!(parse_cpus("0.5").is_ok());
+        for s in ["0.001", "0.000001", "0", "-1", "nan", "inf"] {
+            assert!(parse_cpus(s).is_err(), "{s}");
+        }
+    }
+
+    #[test]
+    fn tmpfs_on_dev_can_hold_device_nodes() {
+        let nodev = |s| {
+            parse_tmpfs(s)
+                .unwrap()
+                .options
+                .contains(&"nodev".to_string())
+        };
+        assert!(!nodev("/dev:size=1m"));
+        assert!(nodev("/tmp"));
+    }
 }
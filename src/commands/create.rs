@@ -1,8 +1,150 @@
-use crate::runtime::{Result, container::Container};
-use std::path::PathBuf;
+use crate::linux::{capabilities, devices, seccomp};
+use crate::runtime::{
+    Result, RuntimeError,
+    container::{Container, CreateOptions},
+    oci,
+    spec::{Hook, HookStage, Mount, NetworkMode, Resources, Rlimit, Spec, Tmpfs},
+};
+use std::path::{Path, PathBuf};
 
-pub fn cmd_create(id: String, rootfs: PathBuf, argv: Vec<String>) -> Result<()> {
-    let c = Container::create(id, rootfs, argv)?;
-    println!("created {}", c.get_id());
+/// Command line settings layered over the spec, whether it came from
+/// `--rootfs` or from a bundle's config.json.
+pub struct SpecArgs {
+    pub overlay: bool,
+    /// Turns it on; a bundle's `root.readonly` stays
+    pub read_only: bool,
+    /// Adds a tmpfs on /tmp and /run where nothing else is mounted
+    pub read_only_tmpfs: bool,
+    /// Added after any from config.json; relative sources are taken from
+    /// the current directory
+    pub mounts: Vec<Mount>,
+    pub tmpfs: Vec<Tmpfs>,
+    /// `(host path, container path, access)`
+    pub devices: Vec<(PathBuf, PathBuf, String)>,
+    pub cap_add: Vec<String>,
+    pub cap_drop: Vec<String>,
+    /// Replaces the built-in profile, or the bundle's; `unconfined` for none
+    pub seccomp_profile: Option<PathBuf>,
+    /// Turns it on; a bundle's `true` stays
+    pub no_new_privileges: bool,
+    /// Replace the bundle's limits of the same type
+    pub ulimits: Vec<Rlimit>,
+    pub oom_score_adj: Option<i32>,
+    pub hooks: Vec<(HookStage, PathBuf)>,
+}
+
+pub fn cmd_create(
+    id: String,
+    rootfs: Option<PathBuf>,
+    argv: Vec<String>,
+    network: NetworkMode,
+    resources: Resources,
+    args: SpecArgs,
+    opts: CreateOptions,
+) -> Result<()> {
+    let spec = build_spec(rootfs, argv, network, resources, args, &opts)?;
+
+    // an engine hands create the container's stdio, keep it clean
+    let quiet = opts.bundle.is_some();
+
+    let c = Container::create(id, spec, opts)?;
+    if !quiet {
+        println!("created {}", c.get_id());
+    }
     Ok(())
 }
+
+/// The spec from `--rootfs` and the command line, or from the bundle with
+/// the command line's additions.
+pub fn build_spec(
+    rootfs: Option<PathBuf>,
+    argv: Vec<String>,
+    network: NetworkMode,
+    resources: Resources,
+    args: SpecArgs,
+    opts: &CreateOptions,
+) -> Result<Spec> {
+    let mut spec = match (&opts.bundle, rootfs) {
+        (Some(bundle), _) => oci::load_bundle(bundle)?,
+        (None, Some(rootfs)) => {
+            let mut spec = Spec::new(rootfs, argv);
+            spec.network = network;
+            spec.resources = resources;
+            spec
+        }
+        // clap requires one of the two
+        (None, None) => unreachable!("create needs --rootfs or --bundle"),
+    };
+
+    spec.overlay = args.overlay;
+    spec.readonly_rootfs |= args.read_only;
+    spec.tmpfs.extend(args.tmpfs);
+    // a bundle without process.capabilities gets the default too, rather
+    // than everything the caller has
+    let mut caps = spec
+        .capabilities
+        .take()
+        .unwrap_or_else(capabilities::default_set);
+    capabilities::adjust(&mut caps, &args.cap_add, &args.cap_drop)?;
+    spec.capabilities = Some(caps);
+
+    // a bundle without linux.seccomp asked for no filter, unlike one
+    // without capabilities
+    match args.seccomp_profile {
+        Some(p) if p == Path::new("unconfined") => spec.seccomp = None,
+        Some(p) => spec.seccomp = Some(seccomp::load_profile(&p)?),
+        None if opts.bundle.is_none() => spec.seccomp = Some(seccomp::default_profile()),
+        None => {}
+    }
+
+    spec.no_new_privileges |= args.no_new_privileges;
+    spec.override_rlimits(args.ulimits);
+    if args.oom_score_adj.is_some() {
+        spec.oom_score_adj = args.oom_score_adj;
+    }
+
+    for (host, path, access) in args.devices {
+        spec.devices
+            .push(devices::host_device(&host, path, access)?);
+    }
+
+    for mut m in args.mounts {
+        if m.kind == "bind"
+            && let Some(src) = &m.source
+        {
+            let abs = src.canonicalize().map_err(|e| {
+                RuntimeError::Msg(format!("bind mount source {}: {e}", src.display()))
+            })?;
+            m.source = Some(abs);
+        }
+        spec.mounts.push(m);
+    }
+
+    if args.read_only_tmpfs {
+        if !spec.readonly_rootfs {
+            return Err(RuntimeError::Msg(
+                "--read-only-tmpfs needs a read-only root (--read-only or root.readonly)".into(),
+            ));
+        }
+        for (dest, mode) in [("/tmp", "1777"), ("/run", "755")] {
+            if !spec.has_mount_at(dest) {
+                spec.tmpfs.push(Tmpfs {
+                    destination: dest.into(),
+                    options: vec!["nosuid".into(), "nodev".into(), format!("mode={mode}")],
+                });
+            }
+        }
+    }
+
+    // run after any from config.json
+    for (stage, path) in args.hooks {
+        spec.hooks.stage_mut(stage).push(Hook {
+            path,
+            args: vec![],
+            env: vec![],
+            timeout: None,
+        });
+    }
+
+    Ok(spec)
+}
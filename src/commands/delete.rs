@@ -1,9 +1,18 @@
-use crate::runtime::{Result, container::Container};
+use crate::runtime::{Result, container::Container, store::Store};
+
+pub fn cmd_delete(id: String, force: bool) -> Result<()> {
+    // like runc, forcing the delete of something already gone is not an error
+    if force && !Store::new()?.exists(&id) {
+        return Ok(());
+    }
 
-pub fn cmd_delete(id: String) -> Result<()> {
     let c = Container::open(id)?;
     let cid = c.get_id().to_string();
-    c.delete()?;
-    println!("deleted {}", cid);
+    // quiet under an engine, as in create
+    let quiet = c.get_state().bundle.is_some();
+    c.delete(force)?;
+    if !quiet {
+        println!("deleted {}", cid);
+    }
     Ok(())
 }
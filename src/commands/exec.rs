@@ -1,15 +1,59 @@
-use crate::runtime::{Result, RuntimeError, container::Container};
+use crate::linux::capabilities;
+use crate::runtime::{
+    Result, RuntimeError,
+    container::{Container, ExecOptions},
+    spec::Rlimit,
+};
 use std::path::PathBuf;
 
-pub fn cmd_exec(
-    id: String,
-    argv: Vec<String>,
-    env: Vec<String>,
-    cwd: Option<PathBuf>,
-) -> Result<()> {
+/// Settings for this one process, on top of the container's spec.
+pub struct ProcessArgs {
+    pub env: Vec<String>,
+    pub cwd: Option<PathBuf>,
+    pub cap_add: Vec<String>,
+    pub cap_drop: Vec<String>,
+    pub ulimits: Vec<Rlimit>,
+    pub no_new_privileges: bool,
+    pub oom_score_adj: Option<i32>,
+}
+
+pub fn cmd_exec(id: String, argv: Vec<String>, args: ProcessArgs) -> Result<()> {
+    let ProcessArgs {
+        env,
+        cwd,
+        cap_add,
+        cap_drop,
+        ulimits,
+        no_new_privileges,
+        oom_score_adj,
+    } = args;
     let env = parse_env_kv(env)?;
     let c = Container::open(id)?;
-    let exit_code = c.exec(argv, env, cwd)?;
+
+    // adjusted from the container's own sets, for this process only
+    let caps = if cap_add.is_empty() && cap_drop.is_empty() {
+        None
+    } else {
+        let mut caps = c
+            .get_spec()
+            .capabilities
+            .clone()
+            .unwrap_or_else(capabilities::default_set);
+        capabilities::adjust(&mut caps, &cap_add, &cap_drop)?;
+        Some(caps)
+    };
+
+    let exit_code = c.exec(
+        argv,
+        ExecOptions {
+            env,
+            cwd,
+            capabilities: caps,
+            rlimits: ulimits,
+            no_new_privileges,
+            oom_score_adj,
+        },
+    )?;
     println!("exitCode={exit_code}");
 
     Ok(())
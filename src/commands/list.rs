@@ -1,4 +1,4 @@
-use crate::runtime::{state::Status, store::Store, Result};
+use crate::runtime::{container::Container, store::Store, Result};
 
 pub fn cmd_list() -> Result<()> {
     let store = Store::new()?;
@@ -14,7 +14,10 @@ pub fn cmd_list() -> Result<()> {
     );
 
     for id in ids {
-        let mut state = match store.load_state(&id) {
+        let state = match Container::open(id.clone()).and_then(|mut c| {
+            c.refresh_state()?;
+            Ok(c.get_state().clone())
+        }) {
             Ok(s) => s,
             Err(err) => {
                 println!(
@@ -25,19 +28,6 @@ pub fn cmd_list() -> Result<()> {
             }
         };
 
-        if matches!(state.status, Status::Running) {
-            let pid_alive = state
-                .pid
-                .map(|pid| std::path::Path::new("/proc").join(pid.to_string()).exists())
-                .unwrap_or(false);
-
-            if !pid_alive {
-                state.status = Status::Stopped;
-                state.pid = None;
-                let _ = store.save_state(&id, &state);
-            }
-        }
-
         let pid_str = state.pid.map(|p| p.to_string()).unwrap_or_else(|| "-".into());
         let started_str = state
             .started_at_unix
@@ -6,3 +6,8 @@ pub mod start;
 pub mod wait;
 pub mod list;
 pub mod state;
+pub mod pause;
+pub mod resume;
+pub mod stats;
+pub mod volume;
+pub mod run;
@@ -0,0 +1,8 @@
+use crate::runtime::{Result, container::Container};
+
+pub fn cmd_pause(id: String) -> Result<()> {
+    let mut c = Container::open(id)?;
+    c.pause()?;
+    println!("paused {}", c.get_id());
+    Ok(())
+}
@@ -0,0 +1,8 @@
+use crate::runtime::{Result, container::Container};
+
+pub fn cmd_resume(id: String) -> Result<()> {
+    let mut c = Container::open(id)?;
+    c.resume()?;
+    println!("resumed {}", c.get_id());
+    Ok(())
+}
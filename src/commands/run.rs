@@ -0,0 +1,36 @@
+use super::create::{SpecArgs, build_spec};
+use crate::runtime::{
+    Result,
+    container::{Container, CreateOptions},
+    spec::{NetworkMode, Resources},
+};
+use std::path::{self, PathBuf};
+
+/// `create`, `start` and `wait` in one go, then `delete`, like `runc run`.
+/// Exits with the container's exit code.
+pub fn cmd_run(
+    id: String,
+    rootfs: Option<PathBuf>,
+    argv: Vec<String>,
+    network: NetworkMode,
+    resources: Resources,
+    args: SpecArgs,
+    mut opts: CreateOptions,
+) -> Result<()> {
+    // written by the supervisor, which need not share our cwd
+    let learn = opts.seccomp_learn.take().map(path::absolute).transpose()?;
+    opts.seccomp_learn = learn.clone();
+
+    let spec = build_spec(rootfs, argv, network, resources, args, &opts)?;
+    let mut c = Container::create(id, spec, opts)?;
+
+    let code = c.start().and_then(|_| c.wait());
+    // a failed start leaves init parked on the FIFO
+    c.delete(code.is_err())?;
+    let code = code?;
+
+    if let Some(out) = learn.filter(|out| out.exists()) {
+        eprintln!("seccomp profile written to {}", out.display());
+    }
+    std::process::exit(code);
+}
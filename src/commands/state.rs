@@ -1,4 +1,4 @@
-use crate::runtime::{container::Container, Result, RuntimeError};
+use crate::runtime::{container::Container, network::Attachment, Result, RuntimeError};
 use serde::Serialize;
 
 #[derive(Serialize)]
@@ -9,6 +9,10 @@ struct StateView<'a> {
     pid_alive: bool,
     created_at_unix: u64,
     started_at_unix: Option<u64>,
+    exit_code: Option<i32>,
+    exit_signal: Option<i32>,
+    finished_at_unix: Option<u64>,
+    network: Option<&'a Attachment>,
 }
 
 pub fn cmd_state(id: String, json: bool) -> Result<()> {
@@ -17,6 +21,14 @@ pub fn cmd_state(id: String, json: bool) -> Result<()> {
 
     let state = c.get_state();
 
+    // what an engine that created the container from a bundle expects
+    if state.bundle.is_some() && !json {
+        let s = serde_json::to_string_pretty(&c.oci_state()?)
+            .map_err(|e| RuntimeError::Msg(e.to_string()))?;
+        println!("{s}");
+        return Ok(());
+    }
+
     let pid_alive = state
         .pid
         .map(|pid| std::path::Path::new("/proc").join(pid.to_string()).exists())
@@ -29,11 +41,15 @@ pub fn cmd_state(id: String, json: bool) -> Result<()> {
         pid_alive,
         created_at_unix: state.created_at_unix,
         started_at_unix: state.started_at_unix,
+        exit_code: state.exit_code,
+        exit_signal: state.exit_signal,
+        finished_at_unix: state.finished_at_unix,
+        network: state.network.as_ref(),
     };
 
     if json {
-        let s = serde_json::to_string_pretty(&view)
-            .map_err(|e| RuntimeError::Msg(e.to_string()))?;
+        let s =
+            serde_json::to_string_pretty(&view).map_err(|e| RuntimeError::Msg(e.to_string()))?;
         println!("{s}");
     } else {
         println!("id: {}", view.id);
@@ -42,6 +58,15 @@ pub fn cmd_state(id: String, json: bool) -> Result<()> {
         println!("pid_alive: {}", view.pid_alive);
         println!("created_at_unix: {}", view.created_at_unix);
         println!("started_at_unix: {:?}", view.started_at_unix);
+        println!("exit_code: {:?}", view.exit_code);
+        println!("exit_signal: {:?}", view.exit_signal);
+        println!("finished_at_unix: {:?}", view.finished_at_unix);
+        if let Some(net) = view.network {
+            println!(
+                "network: {}/{} via {} ({} on {})",
+                net.address, net.prefix_len, net.gateway, net.host_veth, net.bridge
+            );
+        }
     }
 
     Ok(())
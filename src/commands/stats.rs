@@ -0,0 +1,180 @@
+use crate::linux::cgroup::{Cgroup, CgroupStats};
+use crate::runtime::{Result, RuntimeError, container::Container, store::Store};
+use serde::Serialize;
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+const INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Serialize)]
+struct StatsView<'a> {
+    id: &'a str,
+    cpu_percent: f64,
+    memory_usage_bytes: u64,
+    memory_limit_bytes: Option<u64>,
+    memory_percent: Option<f64>,
+    pids: u64,
+    pids_limit: Option<u64>,
+    block_read_bytes: u64,
+    block_write_bytes: u64,
+}
+
+pub fn cmd_stats(ids: Vec<String>, no_stream: bool, json: bool) -> Result<()> {
+    let ids = if ids.is_empty() {
+        live_ids()?
+    } else {
+        for id in &ids {
+            let c = Container::open(id.clone())?;
+            if !c.get_state().status.is_live() || !Cgroup::for_container(id).exists() {
+                return Err(RuntimeError::Msg(format!(
+                    "container {id} is not running in a cgroup"
+                )));
+            }
+        }
+        ids
+    };
+
+    let mut prev = sample(&ids);
+    let mut prev_at = Instant::now();
+
+    loop {
+        // CPU% is a rate, so even --no-stream needs two samples
+        thread::sleep(INTERVAL);
+        let cur = sample(&ids);
+        let now = Instant::now();
+        let elapsed_usec = now.duration_since(prev_at).as_micros().max(1) as f64;
+
+        let views: Vec<StatsView> = ids
+            .iter()
+            .zip(prev.iter().zip(&cur))
+            .map(|(id, (before, after))| view(id, before, after, elapsed_usec))
+            .collect();
+
+        if json {
+            let s = serde_json::to_string(&views).map_err(|e| RuntimeError::Msg(e.to_string()))?;
+            println!("{s}");
+        } else {
+            if !no_stream {
+                // clear the screen so the table updates in place
+                print!("\x1b[2J\x1b[H");
+            }
+            print_table(&views);
+        }
+
+        if no_stream {
+            return Ok(());
+        }
+
+        prev = cur;
+        prev_at = now;
+    }
+}
+
+fn live_ids() -> Result<Vec<String>> {
+    let store = Store::new()?;
+    let mut ids = Vec::new();
+
+    for id in store.list_ids()? {
+        let Ok(mut c) = Container::open(id.clone()) else {
+            continue;
+        };
+        if c.refresh_state().is_err() {
+            continue;
+        }
+        if c.get_state().status.is_live() && Cgroup::for_container(&id).exists() {
+            ids.push(id);
+        }
+    }
+
+    Ok(ids)
+}
+
+/// Containers that stop mid-stream read as zeros rather than failing the loop.
+fn sample(ids: &[String]) -> Vec<CgroupStats> {
+    ids.iter()
+        .map(|id| Cgroup::for_container(id).stats().unwrap_or_default())
+        .collect()
+}
+
+fn view<'a>(
+    id: &'a str,
+    before: &CgroupStats,
+    after: &CgroupStats,
+    elapsed_usec: f64,
+) -> StatsView<'a> {
+    let cpu_delta = after.cpu_usage_usec.saturating_sub(before.cpu_usage_usec) as f64;
+    // page cache that can be reclaimed isn't really "used", same as docker
+    let memory_usage = after
+        .memory_current
+        .saturating_sub(after.memory_inactive_file);
+
+    StatsView {
+        id,
+        cpu_percent: cpu_delta / elapsed_usec * 100.0,
+        memory_usage_bytes: memory_usage,
+        memory_limit_bytes: after.memory_max,
+        memory_percent: after
+            .memory_max
+            .filter(|max| *max > 0)
+            .map(|max| memory_usage as f64 / max as f64 * 100.0),
+        pids: after.pids_current,
+        pids_limit: after.pids_max,
+        block_read_bytes: after.io_read_bytes,
+        block_write_bytes: after.io_write_bytes,
+    }
+}
+
+fn print_table(views: &[StatsView]) {
+    println!(
+        "{:<24} {:<8} {:<24} {:<8} {:<10} {:<24}",
+        "id", "cpu %", "mem usage / limit", "mem %", "pids", "block i/o"
+    );
+
+    for v in views {
+        let limit = v
+            .memory_limit_bytes
+            .map(format_bytes)
+            .unwrap_or_else(|| "max".into());
+        let mem_pct = v
+            .memory_percent
+            .map(|p| format!("{p:.2}%"))
+            .unwrap_or_else(|| "-".into());
+        let pids = match v.pids_limit {
+            Some(max) => format!("{}/{}", v.pids, max),
+            None => v.pids.to_string(),
+        };
+
+        println!(
+            "{:<24} {:<8} {:<24} {:<8} {:<10} {:<24}",
+            v.id,
+            format!("{:.2}%", v.cpu_percent),
+            format!("{} / {}", format_bytes(v.memory_usage_bytes), limit),
+            mem_pct,
+            pids,
+            format!(
+                "{} / {}",
+                format_bytes(v.block_read_bytes),
+                format_bytes(v.block_write_bytes)
+            ),
+        );
+    }
+}
+
+fn format_bytes(n: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+    let mut value = n as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{n}B")
+    } else {
+        format!("{value:.2}{}", UNITS[unit])
+    }
+}
@@ -0,0 +1,53 @@
+use crate::runtime::{Result, RuntimeError, store::Store, volume};
+
+pub fn cmd_create(name: String) -> Result<()> {
+    let vol = volume::create(&Store::new()?, &name)?;
+    println!("{}", vol.name);
+    Ok(())
+}
+
+pub fn cmd_ls() -> Result<()> {
+    let volumes = volume::list(&Store::new()?)?;
+    if volumes.is_empty() {
+        return Ok(());
+    }
+
+    println!("{:<24} {:<6} {:<12}", "name", "users", "created");
+    for vol in volumes {
+        println!(
+            "{:<24} {:<6} {:<12}",
+            vol.name,
+            vol.containers.len(),
+            vol.created_at_unix
+        );
+    }
+    Ok(())
+}
+
+pub fn cmd_inspect(names: Vec<String>) -> Result<()> {
+    let store = Store::new()?;
+    let volumes = names
+        .iter()
+        .map(|name| volume::inspect(&store, name))
+        .collect::<Result<Vec<_>>>()?;
+
+    let s = serde_json::to_string_pretty(&volumes).map_err(|e| RuntimeError::Msg(e.to_string()))?;
+    println!("{s}");
+    Ok(())
+}
+
+pub fn cmd_rm(names: Vec<String>) -> Result<()> {
+    let store = Store::new()?;
+    for name in names {
+        volume::remove(&store, &name)?;
+        println!("{name}");
+    }
+    Ok(())
+}
+
+pub fn cmd_prune() -> Result<()> {
+    for name in volume::prune(&Store::new()?)? {
+        println!("{name}");
+    }
+    Ok(())
+}
@@ -3,6 +3,9 @@ use crate::runtime::{Result, container::Container};
 pub fn cmd_wait(id: String) -> Result<()> {
     let mut c = Container::open(id)?;
     let code = c.wait()?;
-    println!("exitCode={code}");
+    match c.get_state().exit_signal {
+        Some(sig) => println!("exitCode={code} signal={sig}"),
+        None => println!("exitCode={code}"),
+    }
     Ok(())
 }
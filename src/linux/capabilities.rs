@@ -0,0 +1,259 @@
+use crate::runtime::spec::Capabilities;
+use crate::runtime::{Result, RuntimeError};
+use std::{fs, io};
+
+const LINUX_CAPABILITY_VERSION_3: u32 = 0x2008_0522;
+
+/// Index = capability number, see linux/capability.h.
+const NAMES: [&str; 41] = [
+    "CAP_CHOWN",
+    "CAP_DAC_OVERRIDE",
+    "CAP_DAC_READ_SEARCH",
+    "CAP_FOWNER",
+    "CAP_FSETID",
+    "CAP_KILL",
+    "CAP_SETGID",
+    "CAP_SETUID",
+    "CAP_SETPCAP",
+    "CAP_LINUX_IMMUTABLE",
+    "CAP_NET_BIND_SERVICE",
+    "CAP_NET_BROADCAST",
+    "CAP_NET_ADMIN",
+    "CAP_NET_RAW",
+    "CAP_IPC_LOCK",
+    "CAP_IPC_OWNER",
+    "CAP_SYS_MODULE",
+    "CAP_SYS_RAWIO",
+    "CAP_SYS_CHROOT",
+    "CAP_SYS_PTRACE",
+    "CAP_SYS_PACCT",
+    "CAP_SYS_ADMIN",
+    "CAP_SYS_BOOT",
+    "CAP_SYS_NICE",
+    "CAP_SYS_RESOURCE",
+    "CAP_SYS_TIME",
+    "CAP_SYS_TTY_CONFIG",
+    "CAP_MKNOD",
+    "CAP_LEASE",
+    "CAP_AUDIT_WRITE",
+    "CAP_AUDIT_CONTROL",
+    "CAP_SETFCAP",
+    "CAP_MAC_OVERRIDE",
+    "CAP_MAC_ADMIN",
+    "CAP_SYSLOG",
+    "CAP_WAKE_ALARM",
+    "CAP_BLOCK_SUSPEND",
+    "CAP_AUDIT_READ",
+    "CAP_PERFMON",
+    "CAP_BPF",
+    "CAP_CHECKPOINT_RESTORE",
+];
+
+/// Docker's default: what a typical root process in a container needs
+/// (chown, bind low ports, switch users, ...) and nothing that reaches the
+/// host, like CAP_SYS_ADMIN, CAP_NET_ADMIN or CAP_SYS_MODULE.
+const DEFAULT: [&str; 14] = [
+    "CAP_CHOWN",
+    "CAP_DAC_OVERRIDE",
+    "CAP_FSETID",
+    "CAP_FOWNER",
+    "CAP_MKNOD",
+    "CAP_NET_RAW",
+    "CAP_SETGID",
+    "CAP_SETUID",
+    "CAP_SETFCAP",
+    "CAP_SETPCAP",
+    "CAP_NET_BIND_SERVICE",
+    "CAP_SYS_CHROOT",
+    "CAP_KILL",
+    "CAP_AUDIT_WRITE",
+];
+
+/// The sets a container gets when neither the bundle nor the command line
+/// says otherwise.
+pub fn default_set() -> Capabilities {
+    let names: Vec<String> = DEFAULT.iter().map(|n| n.to_string()).collect();
+    Capabilities {
+        bounding: names.clone(),
+        effective: names.clone(),
+        permitted: names,
+        inheritable: vec![],
+        ambient: vec![],
+    }
+}
+
+/// Applies `--cap-drop` and then `--cap-add`, like docker: both act on the
+/// bounding, effective and permitted sets, and `ALL` stands for every
+/// capability.
+pub fn adjust(caps: &mut Capabilities, add: &[String], drop: &[String]) -> Result<()> {
+    let expand = |names: &[String]| -> Result<Vec<String>> {
+        if names.iter().any(|n| n.eq_ignore_ascii_case("ALL")) {
+            return Ok(NAMES.iter().map(|n| n.to_string()).collect());
+        }
+        names
+            .iter()
+            .map(|n| cap_from_name(n).map(|cap| NAMES[cap as usize].to_string()))
+            .collect()
+    };
+    let add = expand(add)?;
+    let drop = expand(drop)?;
+
+    for set in [
+        &mut caps.bounding,
+        &mut caps.effective,
+        &mut caps.permitted,
+        &mut caps.inheritable,
+        &mut caps.ambient,
+    ] {
+        set.retain(|c| !drop.contains(c));
+    }
+    for set in [&mut caps.bounding, &mut caps.effective, &mut caps.permitted] {
+        for cap in &add {
+            if !set.contains(cap) {
+                set.push(cap.clone());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Looks up a capability by name; the `CAP_` prefix and case are optional.
+pub fn cap_from_name(name: &str) -> Result<u32> {
+    let upper = name.to_ascii_uppercase();
+    let full = if upper.starts_with("CAP_") {
+        upper
+    } else {
+        format!("CAP_{upper}")
+    };
+
+    NAMES
+        .iter()
+        .position(|n| *n == full)
+        .map(|i| i as u32)
+        .ok_or_else(|| RuntimeError::Msg(format!("unknown capability: {name}")))
+}
+
+/// Checks every name in `caps` up front, so typos fail at create time rather
+/// than inside the container's pre-exec setup.
+pub fn validate(caps: &Capabilities) -> Result<()> {
+    for name in caps
+        .bounding
+        .iter()
+        .chain(&caps.effective)
+        .chain(&caps.inheritable)
+        .chain(&caps.permitted)
+        .chain(&caps.ambient)
+    {
+        cap_from_name(name)?;
+    }
+    Ok(())
+}
+
+/// Highest capability the running kernel knows about.
+fn last_cap() -> u32 {
+    fs::read_to_string("/proc/sys/kernel/cap_last_cap")
+        .ok()
+        .and_then(|s| s.trim().parse().ok())
+        .unwrap_or(NAMES.len() as u32 - 1)
+}
+
+fn mask(names: &[String]) -> Result<u64> {
+    let last = last_cap();
+    let mut bits = 0u64;
+    for name in names {
+        let cap = cap_from_name(name)?;
+        // newer than the kernel: nothing to grant
+        if cap <= last {
+            bits |= 1 << cap;
+        }
+    }
+    Ok(bits)
+}
+
+/// Drops every capability outside `caps.bounding` from the bounding set.
+/// Needs CAP_SETPCAP, so it runs before the switch to the container user.
+pub fn drop_bounding(caps: &Capabilities) -> Result<()> {
+    let keep = mask(&caps.bounding)?;
+    for cap in 0..=last_cap() {
+        if keep & (1 << cap) == 0 {
+            prctl(libc::PR_CAPBSET_DROP, cap as libc::c_ulong, 0)?;
+        }
+    }
+    Ok(())
+}
+
+/// Sets the effective, permitted and inheritable sets, then the ambient set.
+pub fn apply_sets(caps: &Capabilities) -> Result<()> {
+    let effective = mask(&caps.effective)?;
+    let permitted = mask(&caps.permitted)?;
+    let inheritable = mask(&caps.inheritable)?;
+
+    let header = CapUserHeader {
+        version: LINUX_CAPABILITY_VERSION_3,
+        pid: 0,
+    };
+    // version 3 splits each 64-bit set into two 32-bit words
+    let data = [
+        CapUserData {
+            effective: effective as u32,
+            permitted: permitted as u32,
+            inheritable: inheritable as u32,
+        },
+        CapUserData {
+            effective: (effective >> 32) as u32,
+            permitted: (permitted >> 32) as u32,
+            inheritable: (inheritable >> 32) as u32,
+        },
+    ];
+
+    let rc = unsafe { libc::syscall(libc::SYS_capset, &header, data.as_ptr()) };
+    if rc != 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+
+    prctl(
+        libc::PR_CAP_AMBIENT,
+        libc::PR_CAP_AMBIENT_CLEAR_ALL as libc::c_ulong,
+        0,
+    )?;
+    let ambient = mask(&caps.ambient)?;
+    for cap in 0..=last_cap() {
+        if ambient & (1 << cap) != 0 {
+            prctl(
+                libc::PR_CAP_AMBIENT,
+                libc::PR_CAP_AMBIENT_RAISE as libc::c_ulong,
+                cap as libc::c_ulong,
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Keep permitted capabilities across the setuid() to a non-root user.
+pub fn set_keep_caps(keep: bool) -> Result<()> {
+    prctl(libc::PR_SET_KEEPCAPS, keep as libc::c_ulong, 0)
+}
+
+fn prctl(option: libc::c_int, arg2: libc::c_ulong, arg3: libc::c_ulong) -> Result<()> {
+    let rc = unsafe { libc::prctl(option, arg2, arg3, 0 as libc::c_ulong, 0 as libc::c_ulong) };
+    if rc != 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+/// struct __user_cap_header_struct
+#[repr(C)]
+struct CapUserHeader {
+    version: u32,
+    pid: libc::c_int,
+}
+
+/// struct __user_cap_data_struct
+#[repr(C)]
+struct CapUserData {
+    effective: u32,
+    permitted: u32,
+    inheritable: u32,
+}
@@ -0,0 +1,235 @@
+use super::device_filter;
+use crate::runtime::spec::{DeviceRule, Resources};
+use crate::runtime::{Result, RuntimeError};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::{fs, io, thread, time::Duration};
+
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+const PARENT: &str = "podrun";
+const CPU_PERIOD_US: u64 = 100_000;
+/// The smallest cpu.max quota the kernel accepts
+pub const CPU_MIN_QUOTA_US: u64 = 1_000;
+
+/// Controllers delegated to podrun's subtree whenever the host offers them.
+const CONTROLLERS: [&str; 4] = ["cpu", "memory", "pids", "io"];
+
+#[derive(Debug, Clone, Default)]
+pub struct CgroupStats {
+    pub cpu_usage_usec: u64,
+    pub memory_current: u64,
+    pub memory_inactive_file: u64,
+    pub memory_max: Option<u64>,
+    pub pids_current: u64,
+    pub pids_max: Option<u64>,
+    pub io_read_bytes: u64,
+    pub io_write_bytes: u64,
+}
+
+/// A container's cgroup v2 directory, `/sys/fs/cgroup/podrun/<id>`.
+pub struct Cgroup {
+    path: PathBuf,
+}
+
+impl Cgroup {
+    pub fn for_container(id: &str) -> Self {
+        Self {
+            path: Path::new(CGROUP_ROOT).join(PARENT).join(id),
+        }
+    }
+
+    pub fn exists(&self) -> bool {
+        self.path.exists()
+    }
+
+    /// Whether the host mounts the unified (v2) hierarchy at /sys/fs/cgroup.
+    pub fn supported() -> bool {
+        Path::new(CGROUP_ROOT).join("cgroup.controllers").exists()
+    }
+
+    /// Creates the cgroup if needed and (re)applies `res` to it.
+    pub fn create(&self, res: &Resources) -> Result<()> {
+        let root = Path::new(CGROUP_ROOT);
+        if !Self::supported() {
+            return Err(RuntimeError::Msg(format!(
+                "{CGROUP_ROOT} is not a cgroup v2 mount (unified hierarchy required)"
+            )));
+        }
+
+        let parent = root.join(PARENT);
+        fs::create_dir_all(&parent)?;
+        enable_controllers(root)?;
+        enable_controllers(&parent)?;
+
+        fs::create_dir_all(&self.path)?;
+        self.apply(res)
+    }
+
+    fn apply(&self, res: &Resources) -> Result<()> {
+        if let Some(bytes) = res.memory {
+            self.require("memory")?;
+            self.write("memory.max", &bytes.to_string())?;
+        }
+
+        if let Some(cpus) = res.cpus {
+            self.require("cpu")?;
+            let quota = cpu_quota(cpus);
+            self.write("cpu.max", &format!("{quota} {CPU_PERIOD_US}"))?;
+        }
+
+        if let Some(max) = res.pids_limit {
+            self.require("pids")?;
+            self.write("pids.max", &max.to_string())?;
+        }
+
+        Ok(())
+    }
+
+    /// Replaces the "allow everything" of a fresh cgroup with `rules`.
+    pub fn restrict_devices(&self, rules: &[DeviceRule]) -> Result<()> {
+        device_filter::attach(&self.path, rules)
+    }
+
+    /// Moves `pid` into the cgroup; 0 means the calling process.
+    pub fn add_pid(&self, pid: i32) -> Result<()> {
+        self.write("cgroup.procs", &pid.to_string())
+    }
+
+    /// Freezes or thaws every task in the cgroup and waits until the kernel
+    /// reports the transition as complete.
+    pub fn set_frozen(&self, frozen: bool) -> Result<()> {
+        let want = if frozen { "1" } else { "0" };
+        self.write("cgroup.freeze", want)?;
+
+        for _ in 0..100 {
+            let events = fs::read_to_string(self.path.join("cgroup.events"))?;
+            let done = events
+                .lines()
+                .any(|l| l.strip_prefix("frozen ").map(str::trim) == Some(want));
+            if done {
+                return Ok(());
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        Err(RuntimeError::Msg(format!(
+            "cgroup: timed out waiting for {} to {}",
+            self.path.display(),
+            if frozen { "freeze" } else { "thaw" }
+        )))
+    }
+
+    /// One sample of the cgroup's accounting files. Files for controllers that
+    /// aren't enabled simply read as zero / unlimited.
+    pub fn stats(&self) -> Result<CgroupStats> {
+        let memory_stat = self.read_keyed("memory.stat");
+        let cpu_stat = self.read_keyed("cpu.stat");
+
+        let (mut io_read_bytes, mut io_write_bytes) = (0, 0);
+        if let Ok(io) = fs::read_to_string(self.path.join("io.stat")) {
+            // "<maj>:<min> rbytes=N wbytes=N rios=N ..." per device
+            for (key, value) in io.split_whitespace().filter_map(|kv| kv.split_once('=')) {
+                match key {
+                    "rbytes" => io_read_bytes += value.parse::<u64>().unwrap_or(0),
+                    "wbytes" => io_write_bytes += value.parse::<u64>().unwrap_or(0),
+                    _ => {}
+                }
+            }
+        }
+
+        Ok(CgroupStats {
+            cpu_usage_usec: cpu_stat.get("usage_usec").copied().unwrap_or(0),
+            memory_current: self.read_u64("memory.current").unwrap_or(0),
+            memory_inactive_file: memory_stat.get("inactive_file").copied().unwrap_or(0),
+            memory_max: self.read_u64("memory.max"),
+            pids_current: self.read_u64("pids.current").unwrap_or(0),
+            pids_max: self.read_u64("pids.max"),
+            io_read_bytes,
+            io_write_bytes,
+        })
+    }
+
+    /// Reads a single-value file; `None` for "max" or a missing file.
+    fn read_u64(&self, file: &str) -> Option<u64> {
+        fs::read_to_string(self.path.join(file))
+            .ok()?
+            .trim()
+            .parse()
+            .ok()
+    }
+
+    /// Reads a flat-keyed file such as memory.stat ("key value" per line).
+    fn read_keyed(&self, file: &str) -> HashMap<String, u64> {
+        fs::read_to_string(self.path.join(file))
+            .unwrap_or_default()
+            .lines()
+            .filter_map(|l| {
+                let (k, v) = l.split_once(' ')?;
+                Some((k.to_string(), v.trim().parse().ok()?))
+            })
+            .collect()
+    }
+
+    /// Removes the (empty) cgroup. The kernel may briefly report EBUSY while
+    /// the last exited tasks are still being released.
+    pub fn remove(&self) -> Result<()> {
+        if !self.exists() {
+            return Ok(());
+        }
+
+        for _ in 0..50 {
+            match fs::remove_dir(&self.path) {
+                Ok(()) => return Ok(()),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+                Err(e) if e.raw_os_error() == Some(libc::EBUSY) => {
+                    thread::sleep(Duration::from_millis(10));
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        Err(RuntimeError::Msg(format!(
+            "cgroup still busy: {}",
+            self.path.display()
+        )))
+    }
+
+    fn require(&self, ctrl: &str) -> Result<()> {
+        let available = fs::read_to_string(self.path.join("cgroup.controllers"))?;
+        if !available.split_whitespace().any(|c| c == ctrl) {
+            return Err(RuntimeError::Msg(format!(
+                "cgroup: the {ctrl} controller is not available on this host"
+            )));
+        }
+        Ok(())
+    }
+
+    fn write(&self, file: &str, value: &str) -> Result<()> {
+        fs::write(self.path.join(file), value).map_err(|e| {
+            RuntimeError::Msg(format!(
+                "cgroup: writing {value:?} to {}: {e}",
+                self.path.join(file).display()
+            ))
+        })
+    }
+}
+
+/// The cpu.max quota for `cpus` CPUs, in microseconds per period.
+pub fn cpu_quota(cpus: f64) -> u64 {
+    (cpus * CPU_PERIOD_US as f64).round() as u64
+}
+
+fn enable_controllers(dir: &Path) -> Result<()> {
+    let available = fs::read_to_string(dir.join("cgroup.controllers"))?;
+    let enabled = fs::read_to_string(dir.join("cgroup.subtree_control"))?;
+
+    for ctrl in CONTROLLERS {
+        let offered = available.split_whitespace().any(|c| c == ctrl);
+        let on = enabled.split_whitespace().any(|c| c == ctrl);
+        if offered && !on {
+            fs::write(dir.join("cgroup.subtree_control"), format!("+{ctrl}"))?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,55 @@
+use super::isolation::nix_to_io;
+use crate::runtime::{Result, RuntimeError};
+use nix::pty::{Winsize, openpty};
+use nix::sys::socket::{ControlMessage, MsgFlags, sendmsg};
+use nix::sys::termios::Termios;
+use nix::unistd::setsid;
+use std::io::{self, IoSlice};
+use std::os::fd::{AsRawFd, OwnedFd};
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+
+/// Allocates a pty for init and sends the master to the unix socket at
+/// `console_socket` (SCM_RIGHTS, with the pty path as payload, like runc).
+/// Returns the slave, which becomes init's stdio.
+pub fn send_pty_master(console_socket: &Path) -> Result<OwnedFd> {
+    let pty = openpty(None::<&Winsize>, None::<&Termios>).map_err(nix_to_io)?;
+
+    let stream = UnixStream::connect(console_socket).map_err(|e| {
+        RuntimeError::Msg(format!(
+            "connecting to console socket {}: {e}",
+            console_socket.display()
+        ))
+    })?;
+
+    let name = slave_name(&pty.slave).unwrap_or_else(|| "/dev/ptmx".into());
+    let fds = [pty.master.as_raw_fd()];
+    sendmsg::<()>(
+        stream.as_raw_fd(),
+        &[IoSlice::new(name.as_bytes())],
+        &[ControlMessage::ScmRights(&fds)],
+        MsgFlags::empty(),
+        None,
+    )
+    .map_err(nix_to_io)?;
+
+    // the receiver has its own copy now
+    drop(pty.master);
+    Ok(pty.slave)
+}
+
+fn slave_name(slave: &OwnedFd) -> Option<String> {
+    std::fs::read_link(format!("/proc/self/fd/{}", slave.as_raw_fd()))
+        .ok()
+        .map(|p| p.display().to_string())
+}
+
+/// Makes the pty on stdin the controlling terminal of a new session, so the
+/// shell in the container gets job control and ^C. Runs in pre_exec.
+pub fn set_controlling_tty() -> io::Result<()> {
+    setsid().map_err(nix_to_io)?;
+    if unsafe { libc::ioctl(0, libc::TIOCSCTTY, 0) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
@@ -0,0 +1,397 @@
+//! cgroup v2 has no `devices.allow`; device access is decided by an eBPF
+//! program of type `BPF_PROG_TYPE_CGROUP_DEVICE` attached to the cgroup.
+//! This builds one from a rule list, the same way runc does: rules are
+//! checked last to first and the first match decides, so later rules win,
+//! and anything no rule matches is denied.
+
+use crate::runtime::spec::{DeviceKind, DeviceRule};
+use crate::runtime::{Result, RuntimeError};
+use std::ffi::CString;
+use std::fs::File;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::path::Path;
+
+const BPF_PROG_LOAD: libc::c_long = 5;
+const BPF_PROG_ATTACH: libc::c_long = 8;
+const BPF_PROG_TYPE_CGROUP_DEVICE: u32 = 15;
+const BPF_CGROUP_DEVICE: u32 = 6;
+const BPF_F_ALLOW_MULTI: u32 = 2;
+
+// struct bpf_cgroup_dev_ctx { u32 access_type; u32 major; u32 minor; },
+// access_type being (access << 16) | type
+const BPF_DEVCG_DEV_BLOCK: i32 = 1;
+const BPF_DEVCG_DEV_CHAR: i32 = 2;
+const BPF_DEVCG_ACC_MKNOD: i32 = 1;
+const BPF_DEVCG_ACC_READ: i32 = 2;
+const BPF_DEVCG_ACC_WRITE: i32 = 4;
+const ACC_ALL: i32 = BPF_DEVCG_ACC_MKNOD | BPF_DEVCG_ACC_READ | BPF_DEVCG_ACC_WRITE;
+
+// the few opcodes the program needs
+const LDX_MEM_W: u8 = 0x61;
+const ALU32_AND_K: u8 = 0x54;
+const ALU32_RSH_K: u8 = 0x74;
+const ALU32_MOV_X: u8 = 0xbc;
+const ALU64_MOV_K: u8 = 0xb7;
+const JMP_JNE_K: u8 = 0x55;
+const JMP_JNE_X: u8 = 0x5d;
+const JMP_EXIT: u8 = 0x95;
+
+/// Registers, as set up by the prologue.
+const R0: u8 = 0;
+const R1: u8 = 1; // ctx, then scratch
+const R2: u8 = 2; // device type
+const R3: u8 = 3; // requested access
+const R4: u8 = 4; // major
+const R5: u8 = 5; // minor
+
+/// `struct bpf_insn`
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct Insn {
+    code: u8,
+    /// dst in the low nibble, src in the high one
+    regs: u8,
+    off: i16,
+    imm: i32,
+}
+
+fn insn(code: u8, dst: u8, src: u8, imm: i32) -> Insn {
+    Insn {
+        code,
+        regs: (src << 4) | dst,
+        off: 0,
+        imm,
+    }
+}
+
+/// `dst = *(u32 *)(src + off)`
+fn load_word(dst: u8, src: u8, off: i16) -> Insn {
+    Insn {
+        off,
+        ..insn(LDX_MEM_W, dst, src, 0)
+    }
+}
+
+/// The `BPF_PROG_LOAD` part of `union bpf_attr`.
+#[repr(C)]
+#[derive(Default)]
+struct ProgLoadAttr {
+    prog_type: u32,
+    insn_cnt: u32,
+    insns: u64,
+    license: u64,
+    log_level: u32,
+    log_size: u32,
+    log_buf: u64,
+    kern_version: u32,
+    prog_flags: u32,
+    prog_name: [u8; 16],
+    prog_ifindex: u32,
+    expected_attach_type: u32,
+}
+
+/// The `BPF_PROG_ATTACH` part of `union bpf_attr`.
+#[repr(C)]
+#[derive(Default)]
+struct ProgAttachAttr {
+    target_fd: u32,
+    attach_bpf_fd: u32,
+    attach_type: u32,
+    attach_flags: u32,
+    replace_bpf_fd: u32,
+}
+
+/// Loads a program enforcing `rules` and attaches it to the cgroup at
+/// `cgroup_dir`. The attachment holds its own reference, so it lasts until
+/// the cgroup is removed.
+pub fn attach(cgroup_dir: &Path, rules: &[DeviceRule]) -> Result<()> {
+    let insns = program(rules);
+    let prog =
+        load(&insns).map_err(|e| RuntimeError::Msg(format!("loading the device filter: {e}")))?;
+
+    let cgroup = File::open(cgroup_dir)?;
+    let attr = ProgAttachAttr {
+        target_fd: cgroup.as_raw_fd() as u32,
+        attach_bpf_fd: prog.as_raw_fd() as u32,
+        attach_type: BPF_CGROUP_DEVICE,
+        attach_flags: BPF_F_ALLOW_MULTI,
+        ..Default::default()
+    };
+    bpf(BPF_PROG_ATTACH, &attr).map_err(|e| {
+        RuntimeError::Msg(format!(
+            "attaching the device filter to {}: {e}",
+            cgroup_dir.display()
+        ))
+    })?;
+    Ok(())
+}
+
+fn program(rules: &[DeviceRule]) -> Vec<Insn> {
+    let mut prog = vec![
+        load_word(R2, R1, 0),
+        insn(ALU32_AND_K, R2, 0, 0xffff),
+        load_word(R3, R1, 0),
+        insn(ALU32_RSH_K, R3, 0, 16),
+        load_word(R4, R1, 4),
+        load_word(R5, R1, 8),
+    ];
+
+    for rule in rules.iter().rev() {
+        prog.extend(rule_block(rule));
+        // a rule matching everything decides alone, and the verifier
+        // rejects the unreachable code after it
+        if matches_everything(rule) {
+            return prog;
+        }
+    }
+
+    // nothing matched
+    prog.push(insn(ALU64_MOV_K, R0, 0, 0));
+    prog.push(insn(JMP_EXIT, 0, 0, 0));
+    prog
+}
+
+/// Returns `rule.allow` if the request matches the rule, and otherwise
+/// falls through to the next block.
+fn rule_block(rule: &DeviceRule) -> Vec<Insn> {
+    let mut block = vec![];
+
+    if let Some(kind) = rule.kind {
+        let kind = match kind {
+            DeviceKind::Char => BPF_DEVCG_DEV_CHAR,
+            DeviceKind::Block => BPF_DEVCG_DEV_BLOCK,
+        };
+        block.push(insn(JMP_JNE_K, R2, 0, kind));
+    }
+
+    let access = access_bits(&rule.access);
+    if access != ACC_ALL {
+        // every requested bit must be in the rule's access
+        block.push(insn(ALU32_MOV_X, R1, R3, 0));
+        block.push(insn(ALU32_AND_K, R1, 0, access));
+        block.push(insn(JMP_JNE_X, R1, R3, 0));
+    }
+
+    if let Some(major) = rule.major {
+        block.push(insn(JMP_JNE_K, R4, 0, major as i32));
+    }
+    if let Some(minor) = rule.minor {
+        block.push(insn(JMP_JNE_K, R5, 0, minor as i32));
+    }
+
+    block.push(insn(ALU64_MOV_K, R0, 0, rule.allow as i32));
+    block.push(insn(JMP_EXIT, 0, 0, 0));
+
+    // every mismatch skips to the end of the block
+    let len = block.len();
+    for (i, ins) in block.iter_mut().enumerate() {
+        if ins.code == JMP_JNE_K || ins.code == JMP_JNE_X {
+            ins.off = (len - 1 - i) as i16;
+        }
+    }
+    block
+}
+
+fn matches_everything(rule: &DeviceRule) -> bool {
+    rule.kind.is_none()
+        && rule.major.is_none()
+        && rule.minor.is_none()
+        && access_bits(&rule.access) == ACC_ALL
+}
+
+fn access_bits(access: &str) -> i32 {
+    access.chars().fold(0, |bits, c| {
+        bits | match c {
+            'm' => BPF_DEVCG_ACC_MKNOD,
+            'r' => BPF_DEVCG_ACC_READ,
+            'w' => BPF_DEVCG_ACC_WRITE,
+            _ => 0,
+        }
+    })
+}
+
+/// Loads the program; on rejection, loads it again with the verifier log
+/// switched on so the error says why.
+fn load(insns: &[Insn]) -> io::Result<OwnedFd> {
+    let license = CString::new("MIT").expect("no NUL");
+    let mut attr = ProgLoadAttr {
+        prog_type: BPF_PROG_TYPE_CGROUP_DEVICE,
+        insn_cnt: insns.len() as u32,
+        insns: insns.as_ptr() as u64,
+        license: license.as_ptr() as u64,
+        ..Default::default()
+    };
+    attr.prog_name[..6].copy_from_slice(b"podrun");
+
+    let fd = bpf(BPF_PROG_LOAD, &attr).or_else(|e| {
+        if !matches!(e.raw_os_error(), Some(libc::EACCES | libc::EINVAL)) {
+            return Err(e);
+        }
+        let mut log = vec![0u8; 1 << 16];
+        attr.log_level = 1;
+        attr.log_size = log.len() as u32;
+        attr.log_buf = log.as_mut_ptr() as u64;
+        bpf(BPF_PROG_LOAD, &attr).map_err(|e| {
+            let end = log.iter().position(|&b| b == 0).unwrap_or(log.len());
+            io::Error::other(format!(
+                "{e}: {}",
+                String::from_utf8_lossy(&log[..end]).trim()
+            ))
+        })
+    })?;
+    // the kernel just handed us this fd
+    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+/// bpf(2); returns the new fd for commands that create one, 0 otherwise.
+fn bpf<T>(cmd: libc::c_long, attr: &T) -> io::Result<i32> {
+    // attr is a properly laid out bpf_attr prefix, and any pointers
+    // in it outlive the call
+    let ret = unsafe {
+        libc::syscall(
+            libc::SYS_bpf,
+            cmd,
+            attr as *const T,
+            std::mem::size_of::<T>(),
+        )
+    };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(ret as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs `prog` the way the kernel would for one request, covering the
+    /// instructions `program` emits.
+    fn run(prog: &[Insn], kind: i32, access: i32, major: u32, minor: u32) -> u64 {
+        let ctx = [((access << 16) | kind) as u32, major, minor];
+        let mut r = [0u64; 11];
+        let mut pc = 0;
+        loop {
+            let ins = prog[pc];
+            pc += 1;
+            let (dst, src) = ((ins.regs & 0xf) as usize, (ins.regs >> 4) as usize);
+            let imm = ins.imm as i64 as u64;
+            let jump = match ins.code {
+                LDX_MEM_W => {
+                    assert_eq!(src, R1 as usize, "loads only come from ctx");
+                    r[dst] = ctx[ins.off as usize / 4] as u64;
+                    false
+                }
+                ALU32_AND_K => {
+                    r[dst] = (r[dst] as u32 & ins.imm as u32) as u64;
+                    false
+                }
+                ALU32_RSH_K => {
+                    r[dst] = (r[dst] as u32 >> ins.imm) as u64;
+                    false
+                }
+                ALU32_MOV_X => {
+                    r[dst] = r[src] as u32 as u64;
+                    false
+                }
+                ALU64_MOV_K => {
+                    r[dst] = imm;
+                    false
+                }
+                JMP_JNE_K => r[dst] != imm,
+                JMP_JNE_X => r[dst] != r[src],
+                JMP_EXIT => return r[0],
+                other => panic!("unexpected opcode {other:#x}"),
+            };
+            if jump {
+                pc = (pc as isize + ins.off as isize) as usize;
+            }
+        }
+    }
+
+    fn rule(
+        allow: bool,
+        kind: Option<DeviceKind>,
+        dev: Option<(u32, u32)>,
+        access: &str,
+    ) -> DeviceRule {
+        DeviceRule {
+            allow,
+            kind,
+            major: dev.map(|d| d.0),
+            minor: dev.map(|d| d.1),
+            access: access.into(),
+        }
+    }
+
+    const CHAR: i32 = BPF_DEVCG_DEV_CHAR;
+    const BLOCK: i32 = BPF_DEVCG_DEV_BLOCK;
+    const R: i32 = BPF_DEVCG_ACC_READ;
+    const W: i32 = BPF_DEVCG_ACC_WRITE;
+    const M: i32 = BPF_DEVCG_ACC_MKNOD;
+
+    #[test]
+    fn denies_without_rules() {
+        let prog = program(&[]);
+        assert_eq!(run(&prog, CHAR, R, 1, 3), 0);
+        assert_eq!(run(&prog, BLOCK, M, 8, 0), 0);
+    }
+
+    #[test]
+    fn allows_only_the_matching_device() {
+        let prog = program(&[rule(true, Some(DeviceKind::Char), Some((1, 3)), "rwm")]);
+        assert_eq!(run(&prog, CHAR, R | W, 1, 3), 1);
+        assert_eq!(run(&prog, CHAR, M, 1, 3), 1);
+        assert_eq!(run(&prog, BLOCK, R, 1, 3), 0);
+        assert_eq!(run(&prog, CHAR, R, 1, 5), 0);
+        assert_eq!(run(&prog, CHAR, R, 2, 3), 0);
+    }
+
+    #[test]
+    fn wildcards_match_any_number() {
+        let prog = program(&[rule(true, Some(DeviceKind::Char), None, "m")]);
+        assert_eq!(run(&prog, CHAR, M, 136, 7), 1);
+        assert_eq!(run(&prog, CHAR, R, 136, 7), 0);
+        assert_eq!(run(&prog, BLOCK, M, 8, 0), 0);
+    }
+
+    #[test]
+    fn last_matching_rule_wins() {
+        let prog = program(&[
+            rule(true, None, None, "rwm"),
+            rule(false, Some(DeviceKind::Block), Some((8, 0)), "rw"),
+        ]);
+        assert_eq!(run(&prog, BLOCK, R, 8, 0), 0);
+        assert_eq!(run(&prog, BLOCK, W, 8, 0), 0);
+        // the deny doesn't cover mknod, or other devices
+        assert_eq!(run(&prog, BLOCK, M, 8, 0), 1);
+        assert_eq!(run(&prog, BLOCK, R, 8, 1), 1);
+
+        let prog = program(&[
+            rule(false, Some(DeviceKind::Char), Some((10, 200)), "rwm"),
+            rule(true, Some(DeviceKind::Char), Some((10, 200)), "rwm"),
+        ]);
+        assert_eq!(run(&prog, CHAR, R, 10, 200), 1);
+    }
+
+    #[test]
+    fn every_requested_access_must_be_allowed() {
+        let prog = program(&[rule(true, Some(DeviceKind::Char), Some((10, 200)), "rw")]);
+        assert_eq!(run(&prog, CHAR, R, 10, 200), 1);
+        assert_eq!(run(&prog, CHAR, W, 10, 200), 1);
+        assert_eq!(run(&prog, CHAR, R | W, 10, 200), 1);
+        assert_eq!(run(&prog, CHAR, M, 10, 200), 0);
+        assert_eq!(run(&prog, CHAR, R | M, 10, 200), 0);
+    }
+
+    #[test]
+    fn stops_after_a_rule_matching_everything() {
+        let prog = program(&[
+            rule(true, Some(DeviceKind::Char), Some((1, 3)), "rwm"),
+            rule(false, None, None, "rwm"),
+        ]);
+        assert_eq!(prog.last().map(|i| i.code), Some(JMP_EXIT));
+        assert_eq!(run(&prog, CHAR, R, 1, 3), 0);
+    }
+}
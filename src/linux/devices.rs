@@ -0,0 +1,218 @@
+use super::isolation::{OLD_ROOT, nix_to_io};
+use super::mounts::mount_tmpfs;
+use super::userns::in_user_namespace;
+use crate::runtime::spec::{Device, DeviceKind, DeviceRule, Spec};
+use crate::runtime::{Result, RuntimeError};
+use nix::mount::{MsFlags, mount};
+use nix::sys::stat::{Mode, SFlag, major, makedev, minor, mknod, stat};
+use std::fs::{self, OpenOptions};
+use std::os::unix::fs::{PermissionsExt, symlink};
+use std::path::{Path, PathBuf};
+
+/// The devices the OCI runtime spec promises every container, as
+/// (path, major, minor); all character devices, mode 0666.
+const DEFAULT_DEVICES: [(&str, u64, u64); 6] = [
+    ("/dev/null", 1, 3),
+    ("/dev/zero", 1, 5),
+    ("/dev/full", 1, 7),
+    ("/dev/random", 1, 8),
+    ("/dev/urandom", 1, 9),
+    ("/dev/tty", 5, 0),
+];
+
+/// What the cgroup lets every container use besides [`DEFAULT_DEVICES`]:
+/// /dev/console, /dev/ptmx and the devpts ptys, as (major, minor).
+const DEFAULT_ALLOWED: [(u32, Option<u32>); 3] = [(5, Some(1)), (5, Some(2)), (136, None)];
+
+const SYMLINKS: [(&str, &str); 5] = [
+    ("/proc/self/fd", "/dev/fd"),
+    ("/proc/self/fd/0", "/dev/stdin"),
+    ("/proc/self/fd/1", "/dev/stdout"),
+    ("/proc/self/fd/2", "/dev/stderr"),
+    ("pts/ptmx", "/dev/ptmx"),
+];
+
+const NOSUID_NOEXEC: MsFlags = MsFlags::MS_NOSUID.union(MsFlags::MS_NOEXEC);
+
+/// Mounts the filesystems that belong under a /dev we mounted ourselves:
+/// a private devpts, /dev/shm, and /dev/mqueue when the IPC namespace is
+/// ours to mount it in.
+pub fn mount_dev_filesystems(mqueue: bool) -> Result<()> {
+    fs::create_dir_all("/dev/pts")?;
+    // no gid=5: the tty group may not be mapped in a user namespace
+    mount(
+        Some("devpts"),
+        "/dev/pts",
+        Some("devpts"),
+        NOSUID_NOEXEC,
+        Some("newinstance,ptmxmode=0666,mode=0620"),
+    )
+    .map_err(|e| RuntimeError::Msg(format!("mounting devpts: {e}")))?;
+
+    mount_tmpfs(
+        Path::new("/dev/shm"),
+        &[
+            "nosuid".into(),
+            "nodev".into(),
+            "noexec".into(),
+            "mode=1777".into(),
+            "size=65536k".into(),
+        ],
+    )?;
+
+    if mqueue {
+        fs::create_dir_all("/dev/mqueue")?;
+        mount(
+            Some("mqueue"),
+            "/dev/mqueue",
+            Some("mqueue"),
+            NOSUID_NOEXEC | MsFlags::MS_NODEV,
+            Option::<&str>::None,
+        )
+        .map_err(|e| RuntimeError::Msg(format!("mounting mqueue: {e}")))?;
+    }
+    Ok(())
+}
+
+/// Describes the host device at `host` for `--device`, to show up at `path`
+/// in the container. Symlinks such as /dev/disk/by-id/... are resolved here:
+/// rootless setup binds the node from under the old root, where an absolute
+/// link would point into the container instead.
+pub fn host_device(host: &Path, path: PathBuf, access: String) -> Result<Device> {
+    let host = fs::canonicalize(host)
+        .map_err(|e| RuntimeError::Msg(format!("device {}: {e}", host.display())))?;
+    let st =
+        stat(&host).map_err(|e| RuntimeError::Msg(format!("device {}: {e}", host.display())))?;
+    let kind = match SFlag::from_bits_truncate(st.st_mode & SFlag::S_IFMT.bits()) {
+        SFlag::S_IFCHR => DeviceKind::Char,
+        SFlag::S_IFBLK => DeviceKind::Block,
+        _ => {
+            return Err(RuntimeError::Msg(format!(
+                "{} is not a device node",
+                host.display()
+            )));
+        }
+    };
+
+    Ok(Device {
+        path,
+        host_path: host,
+        kind,
+        major: major(st.st_rdev),
+        minor: minor(st.st_rdev),
+        file_mode: st.st_mode & 0o777,
+        access,
+    })
+}
+
+/// Creates the `--device` nodes. Runs before [`create_default_devices`], so
+/// a device passed in explicitly wins over a default one at the same path.
+pub fn create_devices(devices: &[Device]) -> Result<()> {
+    for d in devices {
+        let kind = match d.kind {
+            DeviceKind::Char => SFlag::S_IFCHR,
+            DeviceKind::Block => SFlag::S_IFBLK,
+        };
+        create_device_node(&d.path, &d.host_path, kind, d.major, d.minor, d.file_mode)?;
+    }
+    Ok(())
+}
+
+/// The container's device policy: OCI `linux.resources.devices` rules
+/// first, then the default allow-list and the `--device` entries, so that
+/// what podrun itself sets up stays usable whatever the bundle denies.
+pub fn policy(spec: &Spec) -> Vec<DeviceRule> {
+    let char_rule = |major, minor| DeviceRule {
+        allow: true,
+        kind: Some(DeviceKind::Char),
+        major: Some(major),
+        minor,
+        access: "rwm".into(),
+    };
+
+    let mut rules = spec.resources.devices.clone();
+    rules.extend(
+        DEFAULT_DEVICES
+            .iter()
+            .map(|&(_, major, minor)| char_rule(major as u32, Some(minor as u32))),
+    );
+    rules.extend(
+        DEFAULT_ALLOWED
+            .iter()
+            .map(|&(major, minor)| char_rule(major, minor)),
+    );
+    // init mknods the node inside the cgroup, and having it again is no
+    // more than having it
+    rules.extend(spec.devices.iter().map(|d| DeviceRule {
+        allow: true,
+        kind: Some(d.kind),
+        major: Some(d.major as u32),
+        minor: Some(d.minor as u32),
+        access: if d.access.contains('m') {
+            d.access.clone()
+        } else {
+            format!("{}m", d.access)
+        },
+    }));
+    rules
+}
+
+/// Creates the default device nodes and /dev symlinks after `pivot_root`,
+/// leaving alone anything a spec mount already put there.
+pub fn create_default_devices() -> Result<()> {
+    for (path, major, minor) in DEFAULT_DEVICES {
+        let path = Path::new(path);
+        create_device_node(path, path, SFlag::S_IFCHR, major, minor, 0o666)?;
+    }
+
+    for (target, link) in SYMLINKS {
+        if fs::symlink_metadata(link).is_err() {
+            symlink(target, link)?;
+        }
+    }
+    Ok(())
+}
+
+/// Makes `dest` the device `major:minor`. mknod(2) is not allowed in a user
+/// namespace, so there the host's node at `host` is bind-mounted instead,
+/// from the old root that is still attached below [`OLD_ROOT`].
+pub fn create_device_node(
+    dest: &Path,
+    host: &Path,
+    kind: SFlag,
+    major: u64,
+    minor: u64,
+    mode: u32,
+) -> Result<()> {
+    if fs::symlink_metadata(dest).is_ok() {
+        return Ok(());
+    }
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    if in_user_namespace() {
+        OpenOptions::new().create_new(true).write(true).open(dest)?;
+        let src = Path::new(OLD_ROOT).join(host.strip_prefix("/").unwrap_or(host));
+        mount(
+            Some(&src),
+            dest,
+            Option::<&str>::None,
+            MsFlags::MS_BIND,
+            Option::<&str>::None,
+        )
+        .map_err(|e| RuntimeError::Msg(format!("binding {}: {e}", host.display())))?;
+        return Ok(());
+    }
+
+    mknod(
+        dest,
+        kind,
+        Mode::from_bits_truncate(mode),
+        makedev(major, minor),
+    )
+    .map_err(nix_to_io)?;
+    // mknod's mode is filtered through the umask
+    fs::set_permissions(dest, fs::Permissions::from_mode(mode))?;
+    Ok(())
+}
@@ -0,0 +1,65 @@
+use super::isolation::nix_to_io;
+use crate::runtime::{Result, RuntimeError};
+use nix::sys::stat::Mode;
+use nix::unistd::mkfifo;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::Path;
+use std::{thread, time::Duration};
+
+/// Creates the FIFO that holds init between `create` and `start`, and opens
+/// it O_PATH so init can still reach it after pivot_root (the fd closes on
+/// exec).
+pub fn create(path: &Path) -> Result<File> {
+    mkfifo(path, Mode::from_bits_truncate(0o622)).map_err(nix_to_io)?;
+    // writable by the container user, whatever the umask
+    fs::set_permissions(path, fs::Permissions::from_mode(0o622))?;
+
+    let f = OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_PATH)
+        .open(path)?;
+    Ok(f)
+}
+
+/// Init side, last thing before exec: blocks until `start` opens the FIFO
+/// for reading, then hands it a byte.
+pub fn wait(fifo: &File) -> io::Result<()> {
+    let mut w = OpenOptions::new()
+        .write(true)
+        .open(format!("/proc/self/fd/{}", fifo.as_raw_fd()))?;
+    w.write_all(b"0")
+}
+
+/// `start` side: lets init go on to exec and removes the FIFO. Fails if init
+/// is gone instead of blocking forever.
+pub fn release(path: &Path, init_pid: i32) -> Result<()> {
+    let mut r = OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(path)
+        .map_err(|e| RuntimeError::Msg(format!("opening exec fifo: {e} (already started?)")))?;
+
+    let mut buf = [0u8; 1];
+    loop {
+        match r.read(&mut buf) {
+            Ok(1) => break,
+            // no writer yet (EOF) or nothing written yet
+            Ok(_) => {}
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        if !Path::new("/proc").join(init_pid.to_string()).exists() {
+            return Err(RuntimeError::Msg(
+                "container init exited before start".into(),
+            ));
+        }
+        thread::sleep(Duration::from_millis(1));
+    }
+
+    fs::remove_file(path)?;
+    Ok(())
+}
@@ -1,16 +1,67 @@
+use super::mounts::remount_tree_readonly;
+use super::netlink::{Netlink, link_index};
 use crate::runtime::Result;
 use nix::{
+    errno::Errno,
     mount::{MntFlags, MsFlags, mount, umount2},
-    sched::{CloneFlags, unshare},
+    sched::{CloneFlags, setns, unshare},
     unistd::{chdir, pivot_root, sethostname},
 };
-use std::{fs, io, path::Path};
+use std::{fs, io, os::fd::AsFd, path::Path};
+
+/// Where `pivot_root` parks the host's root until setup is done with it.
+pub const OLD_ROOT: &str = "/.oldroot";
+
+/// Has to come before every other namespace so that they are owned by it;
+/// the caller's ids mean nothing inside until the parent writes the maps.
+pub fn enter_user_namespace() -> Result<()> {
+    unshare(CloneFlags::CLONE_NEWUSER).map_err(nix_to_io)?;
+    Ok(())
+}
 
 pub fn enter_mount_and_uts_namespaces() -> Result<()> {
     unshare(CloneFlags::CLONE_NEWNS | CloneFlags::CLONE_NEWUTS).map_err(nix_to_io)?;
     Ok(())
 }
 
+/// Unlike the other namespaces, a new PID namespace only applies to children
+/// forked afterwards, so this runs in the process that spawns init (the
+/// supervisor) rather than in init's own setup. The next child becomes PID 1.
+pub fn enter_pid_namespace() -> Result<()> {
+    unshare(CloneFlags::CLONE_NEWPID).map_err(nix_to_io)?;
+    Ok(())
+}
+
+pub fn enter_network_namespace() -> Result<()> {
+    unshare(CloneFlags::CLONE_NEWNET).map_err(nix_to_io)?;
+    Ok(())
+}
+
+/// Joins an existing network namespace, e.g. one created with `ip netns add`.
+pub fn join_network_namespace(path: &Path) -> Result<()> {
+    let f = fs::File::open(path)?;
+    setns(f.as_fd(), CloneFlags::CLONE_NEWNET).map_err(nix_to_io)?;
+    Ok(())
+}
+
+pub fn enter_ipc_namespace() -> Result<()> {
+    unshare(CloneFlags::CLONE_NEWIPC).map_err(nix_to_io)?;
+    Ok(())
+}
+
+/// Must run after joining the container cgroup, which then becomes the root
+/// of the new namespace's view.
+pub fn enter_cgroup_namespace() -> Result<()> {
+    unshare(CloneFlags::CLONE_NEWCGROUP).map_err(nix_to_io)?;
+    Ok(())
+}
+
+/// A fresh network namespace only has `lo`, and it starts out down.
+pub fn bring_up_loopback() -> Result<()> {
+    let index = link_index("lo")?;
+    Netlink::open()?.set_link_up(index)
+}
+
 pub fn make_mounts_private() -> Result<()> {
     mount::<str, str, str, str>(None, "/", None, MsFlags::MS_REC | MsFlags::MS_PRIVATE, None)
         .map_err(nix_to_io)?;
@@ -30,7 +81,7 @@ pub fn ensure_rootfs_is_mountpoint(rootfs: &Path) -> Result<()> {
 }
 
 pub fn pivot_root_into(rootfs: &Path) -> Result<()> {
-    let put_old = rootfs.join(".oldroot");
+    let put_old = rootfs.join(OLD_ROOT.trim_start_matches('/'));
     fs::create_dir_all(&put_old)?;
     pivot_root(rootfs, &put_old).map_err(nix_to_io)?;
     Ok(())
@@ -54,17 +105,37 @@ pub fn mount_proc() -> Result<()> {
     Ok(())
 }
 
-pub fn mount_minimal_dev() -> Result<()> {
-    fs::create_dir_all("/dev")?;
-    mount(
-        Some("tmpfs"),
-        "/dev",
-        Some("tmpfs"),
-        MsFlags::empty(),
-        Some("mode=755"),
-    )
-    .map_err(nix_to_io)?;
-    Ok(())
+/// Read-only: the container gets to look at devices and kernel objects,
+/// not to reconfigure them.
+pub fn mount_sys() -> Result<()> {
+    fs::create_dir_all("/sys")?;
+    let flags = MsFlags::MS_RDONLY | MsFlags::MS_NOSUID | MsFlags::MS_NODEV | MsFlags::MS_NOEXEC;
+    match mount(
+        Some("sysfs"),
+        "/sys",
+        Some("sysfs"),
+        flags,
+        Option::<&str>::None,
+    ) {
+        // A user namespace may only mount sysfs for a network namespace it
+        // owns. Sharing the host's network, it gets the host's /sys instead.
+        Err(Errno::EPERM) => {
+            let host_sys = Path::new(OLD_ROOT).join("sys");
+            mount(
+                Some(&host_sys),
+                "/sys",
+                Option::<&str>::None,
+                MsFlags::MS_BIND | MsFlags::MS_REC,
+                Option::<&str>::None,
+            )
+            .map_err(nix_to_io)?;
+            remount_tree_readonly(Path::new("/sys"))
+        }
+        res => {
+            res.map_err(nix_to_io)?;
+            Ok(())
+        }
+    }
 }
 
 pub fn set_container_hostname(hostname: Option<&str>) -> Result<()> {
@@ -75,12 +146,12 @@ pub fn set_container_hostname(hostname: Option<&str>) -> Result<()> {
 }
 
 pub fn detach_and_remove_old_root() -> Result<()> {
-    umount2("/.oldroot", MntFlags::MNT_DETACH).map_err(nix_to_io)?;
-    let _ = fs::remove_dir_all("/.oldroot");
+    umount2(OLD_ROOT, MntFlags::MNT_DETACH).map_err(nix_to_io)?;
+    let _ = fs::remove_dir_all(OLD_ROOT);
     Ok(())
 }
 
 // TODO: Separate into a separate file
 pub fn nix_to_io<E: std::fmt::Display>(e: E) -> io::Error {
-    io::Error::new(io::ErrorKind::Other, e.to_string())
+    io::Error::other(e.to_string())
 }
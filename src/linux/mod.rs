@@ -1,2 +1,15 @@
+pub mod capabilities;
+pub mod cgroup;
+pub mod console;
+pub mod device_filter;
+pub mod devices;
+pub mod exec_fifo;
 pub mod isolation;
+pub mod mounts;
+pub mod netlink;
+pub mod process;
+pub mod seccomp;
+pub mod seccomp_learn;
 pub mod setup;
+mod syscalls;
+pub mod userns;
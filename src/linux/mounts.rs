@@ -0,0 +1,449 @@
+use super::cgroup::Cgroup;
+use super::userns::in_user_namespace;
+use crate::runtime::spec::Mount;
+use crate::runtime::{Result, RuntimeError};
+use nix::mount::{MsFlags, mount};
+use nix::sys::statvfs::{FsFlags, statvfs};
+use std::ffi::OsString;
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::os::unix::ffi::OsStringExt;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Component, Path, PathBuf};
+
+const MAX_SYMLINK_HOPS: usize = 40;
+
+/// `Mount::options` split into what mount(2) takes as flags, what has to be
+/// applied as a separate propagation change, and filesystem-specific data.
+#[derive(Debug)]
+pub struct MountOptions {
+    pub flags: MsFlags,
+    pub propagation: MsFlags,
+    pub data: Vec<String>,
+}
+
+pub fn parse_options(options: &[String]) -> MountOptions {
+    let mut out = MountOptions {
+        flags: MsFlags::empty(),
+        propagation: MsFlags::empty(),
+        data: vec![],
+    };
+
+    for opt in options {
+        let (set, clear) = match opt.as_str() {
+            "defaults" => (MsFlags::empty(), MsFlags::empty()),
+            "ro" => (MsFlags::MS_RDONLY, MsFlags::empty()),
+            "rw" => (MsFlags::empty(), MsFlags::MS_RDONLY),
+            "nosuid" => (MsFlags::MS_NOSUID, MsFlags::empty()),
+            "suid" => (MsFlags::empty(), MsFlags::MS_NOSUID),
+            "nodev" => (MsFlags::MS_NODEV, MsFlags::empty()),
+            "dev" => (MsFlags::empty(), MsFlags::MS_NODEV),
+            "noexec" => (MsFlags::MS_NOEXEC, MsFlags::empty()),
+            "exec" => (MsFlags::empty(), MsFlags::MS_NOEXEC),
+            "sync" => (MsFlags::MS_SYNCHRONOUS, MsFlags::empty()),
+            "async" => (MsFlags::empty(), MsFlags::MS_SYNCHRONOUS),
+            "dirsync" => (MsFlags::MS_DIRSYNC, MsFlags::empty()),
+            "mand" => (MsFlags::MS_MANDLOCK, MsFlags::empty()),
+            "nomand" => (MsFlags::empty(), MsFlags::MS_MANDLOCK),
+            "noatime" => (MsFlags::MS_NOATIME, MsFlags::empty()),
+            "atime" => (MsFlags::empty(), MsFlags::MS_NOATIME),
+            "nodiratime" => (MsFlags::MS_NODIRATIME, MsFlags::empty()),
+            "diratime" => (MsFlags::empty(), MsFlags::MS_NODIRATIME),
+            "relatime" => (MsFlags::MS_RELATIME, MsFlags::empty()),
+            "norelatime" => (MsFlags::empty(), MsFlags::MS_RELATIME),
+            "strictatime" => (MsFlags::MS_STRICTATIME, MsFlags::empty()),
+            "bind" => (MsFlags::MS_BIND, MsFlags::empty()),
+            "rbind" => (MsFlags::MS_BIND | MsFlags::MS_REC, MsFlags::empty()),
+            other => {
+                match propagation_flags(other) {
+                    Some(p) => out.propagation = p,
+                    None => out.data.push(other.to_string()),
+                }
+                continue;
+            }
+        };
+        out.flags.insert(set);
+        out.flags.remove(clear);
+    }
+
+    out
+}
+
+pub fn propagation_flags(name: &str) -> Option<MsFlags> {
+    let flags = match name {
+        "private" => MsFlags::MS_PRIVATE,
+        "rprivate" => MsFlags::MS_PRIVATE | MsFlags::MS_REC,
+        "shared" => MsFlags::MS_SHARED,
+        "rshared" => MsFlags::MS_SHARED | MsFlags::MS_REC,
+        "slave" => MsFlags::MS_SLAVE,
+        "rslave" => MsFlags::MS_SLAVE | MsFlags::MS_REC,
+        "unbindable" => MsFlags::MS_UNBINDABLE,
+        "runbindable" => MsFlags::MS_UNBINDABLE | MsFlags::MS_REC,
+        _ => return None,
+    };
+    Some(flags)
+}
+
+/// Writable layer of a copy-on-write rootfs, kept in the container's store
+/// directory so `delete` discards it along with everything else.
+#[derive(Debug, Clone)]
+pub struct Overlay {
+    pub upper: PathBuf,
+    pub work: PathBuf,
+    pub merged: PathBuf,
+}
+
+impl Overlay {
+    pub fn in_dir(dir: &Path) -> Self {
+        Self {
+            upper: dir.join("upper"),
+            work: dir.join("work"),
+            merged: dir.join("merged"),
+        }
+    }
+
+    pub fn create_dirs(&self) -> Result<()> {
+        for d in [&self.upper, &self.work, &self.merged] {
+            fs::create_dir_all(d)?;
+        }
+        Ok(())
+    }
+
+    /// Mounts `lower` read-only under the upper layer at `merged`.
+    pub fn mount(&self, lower: &Path) -> Result<()> {
+        let mut data = format!(
+            "lowerdir={},upperdir={},workdir={}",
+            lower.display(),
+            self.upper.display(),
+            self.work.display()
+        );
+        // trusted.* xattrs are off limits without real root
+        if in_user_namespace() {
+            data.push_str(",userxattr");
+        }
+
+        mount(
+            Some("overlay"),
+            &self.merged,
+            Some("overlay"),
+            MsFlags::empty(),
+            Some(data.as_str()),
+        )
+        .map_err(|e| RuntimeError::Msg(format!("mounting overlay rootfs: {e}")))?;
+        Ok(())
+    }
+
+    /// The kernel leaves `work/work` mode 000, which only real root can
+    /// remove without fixing it up first.
+    pub fn prepare_removal(dir: &Path) {
+        let _ = fs::set_permissions(
+            Self::in_dir(dir).work.join("work"),
+            fs::Permissions::from_mode(0o700),
+        );
+    }
+}
+
+/// Performs one spec mount inside `rootfs`, before `pivot_root`.
+pub fn mount_in_rootfs(rootfs: &Path, m: &Mount) -> Result<()> {
+    let target = resolve_in_rootfs(rootfs, &m.destination)?;
+    let opts = parse_options(&m.options);
+
+    if m.kind == "bind" || opts.flags.contains(MsFlags::MS_BIND) {
+        let src = m.source.as_deref().ok_or_else(|| {
+            RuntimeError::Msg(format!(
+                "bind mount on {} has no source",
+                m.destination.display()
+            ))
+        })?;
+        create_mountpoint(&target, src.is_dir())?;
+
+        let rec = opts.flags & MsFlags::MS_REC;
+        mount(
+            Some(src),
+            &target,
+            Option::<&str>::None,
+            MsFlags::MS_BIND | rec,
+            Option::<&str>::None,
+        )
+        .map_err(|e| mount_error(m, e))?;
+
+        // MS_BIND ignores every other flag, so ro/nosuid/... need a remount
+        let extra = opts.flags - MsFlags::MS_BIND - MsFlags::MS_REC;
+        if !extra.is_empty() {
+            let locked = current_flags(&target).map_err(|e| mount_error(m, e))?;
+            mount(
+                Option::<&str>::None,
+                &target,
+                Option::<&str>::None,
+                MsFlags::MS_REMOUNT | MsFlags::MS_BIND | locked | extra,
+                Option::<&str>::None,
+            )
+            .map_err(|e| mount_error(m, e))?;
+        }
+    } else {
+        create_mountpoint(&target, true)?;
+
+        let kind = filesystem_type(&m.kind)?;
+        let source = m
+            .source
+            .as_deref()
+            .map(|s| s.as_os_str().to_owned())
+            .unwrap_or_else(|| OsString::from(kind));
+        let data = opts.data.join(",");
+
+        mount(
+            Some(source.as_os_str()),
+            &target,
+            Some(kind),
+            opts.flags,
+            (!data.is_empty()).then_some(data.as_str()),
+        )
+        .map_err(|e| mount_error(m, e))?;
+    }
+
+    if !opts.propagation.is_empty() {
+        mount(
+            Option::<&str>::None,
+            &target,
+            Option::<&str>::None,
+            opts.propagation,
+            Option::<&str>::None,
+        )
+        .map_err(|e| mount_error(m, e))?;
+    }
+
+    Ok(())
+}
+
+/// Flags the mount at `path` already has. Inside a user namespace a remount
+/// that drops any of them fails with EPERM, so a remount has to repeat them.
+fn current_flags(path: &Path) -> nix::Result<MsFlags> {
+    let st = statvfs(path)?.flags();
+    let mut flags = MsFlags::empty();
+    for (f, m) in [
+        (FsFlags::ST_RDONLY, MsFlags::MS_RDONLY),
+        (FsFlags::ST_NOSUID, MsFlags::MS_NOSUID),
+        (FsFlags::ST_NODEV, MsFlags::MS_NODEV),
+        (FsFlags::ST_NOEXEC, MsFlags::MS_NOEXEC),
+        (FsFlags::ST_NOATIME, MsFlags::MS_NOATIME),
+        (FsFlags::ST_NODIRATIME, MsFlags::MS_NODIRATIME),
+        (FsFlags::ST_RELATIME, MsFlags::MS_RELATIME),
+    ] {
+        if st.contains(f) {
+            flags.insert(m);
+        }
+    }
+    Ok(flags)
+}
+
+/// Mounts a tmpfs at `dest` in the current root, with `options` as for
+/// `--tmpfs` (mount flags plus tmpfs's own size=, mode=, ...).
+pub fn mount_tmpfs(dest: &Path, options: &[String]) -> Result<()> {
+    let opts = parse_options(options);
+    let data = opts.data.join(",");
+    fs::create_dir_all(dest)?;
+
+    mount(
+        Some("tmpfs"),
+        dest,
+        Some("tmpfs"),
+        opts.flags,
+        (!data.is_empty()).then_some(data.as_str()),
+    )
+    .map_err(|e| RuntimeError::Msg(format!("mounting tmpfs on {}: {e}", dest.display())))?;
+    Ok(())
+}
+
+/// Hides `path` in the current root: /dev/null over a file, an empty
+/// read-only tmpfs over a directory. Nothing to hide if it doesn't exist.
+pub fn mask_path(path: &Path) -> Result<()> {
+    let meta = match fs::metadata(path) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        meta => meta?,
+    };
+
+    let res = if meta.is_dir() {
+        mount(
+            Some("tmpfs"),
+            path,
+            Some("tmpfs"),
+            MsFlags::MS_RDONLY,
+            Option::<&str>::None,
+        )
+    } else {
+        mount(
+            Some("/dev/null"),
+            path,
+            Option::<&str>::None,
+            MsFlags::MS_BIND,
+            Option::<&str>::None,
+        )
+    };
+    res.map_err(|e| RuntimeError::Msg(format!("masking {}: {e}", path.display())))
+}
+
+/// Makes `path` in the current root read-only by binding it onto itself and
+/// remounting the bind and every mount below it. Skipped if it doesn't exist.
+pub fn make_readonly(path: &Path) -> Result<()> {
+    if !path.exists() {
+        return Ok(());
+    }
+
+    mount(
+        Some(path),
+        path,
+        Option::<&str>::None,
+        MsFlags::MS_BIND | MsFlags::MS_REC,
+        Option::<&str>::None,
+    )
+    .map_err(|e| RuntimeError::Msg(format!("making {} read-only: {e}", path.display())))?;
+    remount_tree_readonly(path)
+}
+
+/// Remounts the mount at `path` read-only, keeping its other flags.
+pub fn remount_readonly(path: &Path) -> Result<()> {
+    let err = |e: nix::errno::Errno| {
+        RuntimeError::Msg(format!("making {} read-only: {e}", path.display()))
+    };
+    let locked = current_flags(path).map_err(err)?;
+    mount(
+        Option::<&str>::None,
+        path,
+        Option::<&str>::None,
+        MsFlags::MS_REMOUNT | MsFlags::MS_BIND | MsFlags::MS_RDONLY | locked,
+        Option::<&str>::None,
+    )
+    .map_err(err)?;
+    Ok(())
+}
+
+/// Remounts the mount at `path` and every mount below it read-only, for
+/// recursive binds: the remount itself only applies to the top one.
+pub fn remount_tree_readonly(path: &Path) -> Result<()> {
+    let mountinfo = fs::read_to_string("/proc/self/mountinfo")?;
+    // parents come before their children
+    for point in mountinfo.lines().filter_map(|l| l.split(' ').nth(4)) {
+        let point = unescape_mount_point(point);
+        if point.starts_with(path) {
+            remount_readonly(&point)?;
+        }
+    }
+    Ok(())
+}
+
+/// Undoes mountinfo's octal escapes (`\040` for a space and so on).
+fn unescape_mount_point(s: &str) -> PathBuf {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let octal = bytes.get(i + 1..i + 4).and_then(|d| {
+            std::str::from_utf8(d)
+                .ok()
+                .and_then(|d| u8::from_str_radix(d, 8).ok())
+        });
+        match (bytes[i], octal) {
+            (b'\\', Some(b)) => {
+                out.push(b);
+                i += 4;
+            }
+            (b, _) => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    PathBuf::from(OsString::from_vec(out))
+}
+
+/// OCI configs say "cgroup" and leave the version to the runtime.
+fn filesystem_type(kind: &str) -> Result<&str> {
+    match kind {
+        "cgroup" | "cgroup2" if Cgroup::supported() => Ok("cgroup2"),
+        "cgroup" | "cgroup2" => Err(RuntimeError::Msg(
+            "cgroup mounts need a cgroup v2 host".into(),
+        )),
+        other => Ok(other),
+    }
+}
+
+fn mount_error(m: &Mount, e: nix::errno::Errno) -> RuntimeError {
+    RuntimeError::Msg(format!(
+        "mounting {} ({}) on {}: {e}",
+        m.source
+            .as_deref()
+            .map(|s| s.display().to_string())
+            .unwrap_or_else(|| m.kind.clone()),
+        m.kind,
+        m.destination.display()
+    ))
+}
+
+fn create_mountpoint(target: &Path, dir: bool) -> Result<()> {
+    if dir {
+        fs::create_dir_all(target)?;
+        return Ok(());
+    }
+
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if !target.exists() {
+        OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(target)?;
+    }
+    Ok(())
+}
+
+/// Joins `dest` onto `rootfs` the way the container will see it: symlinks in
+/// the image are resolved relative to the rootfs, so they can't point a mount
+/// at the host.
+pub fn resolve_in_rootfs(rootfs: &Path, dest: &Path) -> Result<PathBuf> {
+    let mut resolved = PathBuf::new();
+    let mut pending: Vec<OsString> = components(dest);
+    pending.reverse();
+    let mut hops = 0;
+
+    while let Some(name) = pending.pop() {
+        if name == ".." {
+            resolved.pop();
+            continue;
+        }
+
+        let candidate = resolved.join(&name);
+        let host = rootfs.join(&candidate);
+        match fs::symlink_metadata(&host) {
+            Ok(md) if md.file_type().is_symlink() => {
+                hops += 1;
+                if hops > MAX_SYMLINK_HOPS {
+                    return Err(RuntimeError::Msg(format!(
+                        "too many symlinks resolving {}",
+                        dest.display()
+                    )));
+                }
+
+                let link = fs::read_link(&host)?;
+                if link.is_absolute() {
+                    resolved = PathBuf::new();
+                }
+                pending.extend(components(&link).into_iter().rev());
+            }
+            _ => resolved = candidate,
+        }
+    }
+
+    Ok(rootfs.join(resolved))
+}
+
+fn components(p: &Path) -> Vec<OsString> {
+    p.components()
+        .filter_map(|c| match c {
+            Component::Normal(n) => Some(n.to_owned()),
+            Component::ParentDir => Some(OsString::from("..")),
+            _ => None,
+        })
+        .collect()
+}
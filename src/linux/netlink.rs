@@ -0,0 +1,275 @@
+use crate::runtime::{Result, RuntimeError};
+use std::ffi::CString;
+use std::io;
+use std::net::Ipv4Addr;
+use std::os::fd::{AsRawFd, BorrowedFd, FromRawFd, OwnedFd};
+
+const NLMSG_HDRLEN: usize = 16;
+const VETH_INFO_PEER: u16 = 1;
+
+/// Minimal rtnetlink client: just enough to build requests and wait for the
+/// kernel's ACK, without pulling in a full netlink crate.
+pub struct Netlink {
+    fd: OwnedFd,
+    seq: u32,
+}
+
+impl Netlink {
+    pub fn open() -> Result<Self> {
+        let fd = unsafe {
+            libc::socket(
+                libc::AF_NETLINK,
+                libc::SOCK_RAW | libc::SOCK_CLOEXEC,
+                libc::NETLINK_ROUTE,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+
+        Ok(Self {
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+            seq: 0,
+        })
+    }
+
+    pub fn set_link_up(&mut self, index: u32) -> Result<()> {
+        let mut msg = Message::new(libc::RTM_NEWLINK, 0);
+        msg.ifinfo(index, libc::IFF_UP as u32, libc::IFF_UP as u32);
+        self.request(msg)
+    }
+
+    /// Creates a bridge device. An existing device with the same name is not
+    /// an error, so callers can use this as "ensure".
+    pub fn create_bridge(&mut self, name: &str) -> Result<()> {
+        let mut msg = Message::new(libc::RTM_NEWLINK, create_flags());
+        msg.ifinfo(0, 0, 0);
+        msg.attr_str(libc::IFLA_IFNAME, name);
+        let info = msg.begin_nested(libc::IFLA_LINKINFO);
+        msg.attr_str(libc::IFLA_INFO_KIND, "bridge");
+        msg.end_nested(info);
+        ignore_exists(self.request(msg))
+    }
+
+    pub fn create_veth(&mut self, name: &str, peer: &str) -> Result<()> {
+        let mut msg = Message::new(libc::RTM_NEWLINK, create_flags());
+        msg.ifinfo(0, 0, 0);
+        msg.attr_str(libc::IFLA_IFNAME, name);
+        let info = msg.begin_nested(libc::IFLA_LINKINFO);
+        msg.attr_str(libc::IFLA_INFO_KIND, "veth");
+        let data = msg.begin_nested(libc::IFLA_INFO_DATA);
+        let peer_info = msg.begin_nested(VETH_INFO_PEER);
+        msg.ifinfo(0, 0, 0);
+        msg.attr_str(libc::IFLA_IFNAME, peer);
+        msg.end_nested(peer_info);
+        msg.end_nested(data);
+        msg.end_nested(info);
+        self.request(msg)
+    }
+
+    pub fn delete_link(&mut self, index: u32) -> Result<()> {
+        let mut msg = Message::new(libc::RTM_DELLINK, 0);
+        msg.ifinfo(index, 0, 0);
+        self.request(msg)
+    }
+
+    pub fn set_link_master(&mut self, index: u32, master: u32) -> Result<()> {
+        let mut msg = Message::new(libc::RTM_NEWLINK, 0);
+        msg.ifinfo(index, 0, 0);
+        msg.attr_u32(libc::IFLA_MASTER, master);
+        self.request(msg)
+    }
+
+    /// The link must be down to be renamed.
+    pub fn set_link_name(&mut self, index: u32, name: &str) -> Result<()> {
+        let mut msg = Message::new(libc::RTM_NEWLINK, 0);
+        msg.ifinfo(index, 0, 0);
+        msg.attr_str(libc::IFLA_IFNAME, name);
+        self.request(msg)
+    }
+
+    /// Moves a link into the network namespace referred to by `netns`.
+    pub fn set_link_netns(&mut self, index: u32, netns: BorrowedFd<'_>) -> Result<()> {
+        let mut msg = Message::new(libc::RTM_NEWLINK, 0);
+        msg.ifinfo(index, 0, 0);
+        msg.attr_u32(libc::IFLA_NET_NS_FD, netns.as_raw_fd() as u32);
+        self.request(msg)
+    }
+
+    /// Adds an IPv4 address; an address that is already present is not an error.
+    pub fn add_address(&mut self, index: u32, addr: Ipv4Addr, prefix_len: u8) -> Result<()> {
+        let mut msg = Message::new(libc::RTM_NEWADDR, create_flags());
+        // struct ifaddrmsg
+        msg.buf.push(libc::AF_INET as u8);
+        msg.buf.push(prefix_len);
+        msg.buf.push(0); // flags
+        msg.buf.push(libc::RT_SCOPE_UNIVERSE);
+        msg.buf.extend_from_slice(&index.to_ne_bytes());
+        msg.attr(libc::IFA_LOCAL, &addr.octets());
+        msg.attr(libc::IFA_ADDRESS, &addr.octets());
+        ignore_exists(self.request(msg))
+    }
+
+    pub fn add_default_route(&mut self, gateway: Ipv4Addr) -> Result<()> {
+        let mut msg = Message::new(libc::RTM_NEWROUTE, create_flags());
+        // struct rtmsg
+        msg.buf.push(libc::AF_INET as u8);
+        msg.buf.push(0); // dst_len
+        msg.buf.push(0); // src_len
+        msg.buf.push(0); // tos
+        msg.buf.push(libc::RT_TABLE_MAIN);
+        msg.buf.push(libc::RTPROT_BOOT);
+        msg.buf.push(libc::RT_SCOPE_UNIVERSE);
+        msg.buf.push(libc::RTN_UNICAST);
+        msg.buf.extend_from_slice(&0u32.to_ne_bytes()); // flags
+        msg.attr(libc::RTA_GATEWAY, &gateway.octets());
+        self.request(msg)
+    }
+
+    fn request(&mut self, mut msg: Message) -> Result<()> {
+        self.seq += 1;
+        let buf = msg.finish(self.seq);
+
+        let n = unsafe { libc::send(self.fd.as_raw_fd(), buf.as_ptr().cast(), buf.len(), 0) };
+        if n < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+
+        self.wait_ack()
+    }
+
+    fn wait_ack(&mut self) -> Result<()> {
+        let mut buf = vec![0u8; 8192];
+
+        loop {
+            let n =
+                unsafe { libc::recv(self.fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len(), 0) };
+            if n < 0 {
+                let err = io::Error::last_os_error();
+                if err.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(err.into());
+            }
+
+            let mut off = 0usize;
+            while off + NLMSG_HDRLEN <= n as usize {
+                let len = u32::from_ne_bytes(buf[off..off + 4].try_into().unwrap()) as usize;
+                let ty = u16::from_ne_bytes(buf[off + 4..off + 6].try_into().unwrap());
+                let seq = u32::from_ne_bytes(buf[off + 8..off + 12].try_into().unwrap());
+
+                if len < NLMSG_HDRLEN {
+                    return Err(RuntimeError::Msg("netlink: malformed reply".into()));
+                }
+
+                if seq == self.seq && ty == libc::NLMSG_ERROR as u16 {
+                    let start = off + NLMSG_HDRLEN;
+                    let errno = i32::from_ne_bytes(buf[start..start + 4].try_into().unwrap());
+                    if errno == 0 {
+                        return Ok(());
+                    }
+                    return Err(io::Error::from_raw_os_error(-errno).into());
+                }
+
+                off += align(len);
+            }
+        }
+    }
+}
+
+/// Kernel index of a network interface in the current namespace.
+pub fn link_index(name: &str) -> Result<u32> {
+    let c = CString::new(name).map_err(|e| RuntimeError::Msg(e.to_string()))?;
+    let index = unsafe { libc::if_nametoindex(c.as_ptr()) };
+    if index == 0 {
+        return Err(RuntimeError::Msg(format!(
+            "network interface not found: {name}"
+        )));
+    }
+    Ok(index)
+}
+
+struct Message {
+    buf: Vec<u8>,
+}
+
+impl Message {
+    fn new(ty: u16, flags: u16) -> Self {
+        let flags = flags | (libc::NLM_F_REQUEST | libc::NLM_F_ACK) as u16;
+
+        let mut buf = Vec::with_capacity(256);
+        buf.extend_from_slice(&0u32.to_ne_bytes()); // len, patched in finish
+        buf.extend_from_slice(&ty.to_ne_bytes());
+        buf.extend_from_slice(&flags.to_ne_bytes());
+        buf.extend_from_slice(&0u32.to_ne_bytes()); // seq, patched in finish
+        buf.extend_from_slice(&0u32.to_ne_bytes()); // port id, 0 = kernel
+        Self { buf }
+    }
+
+    /// struct ifinfomsg
+    fn ifinfo(&mut self, index: u32, flags: u32, change: u32) {
+        self.buf.push(libc::AF_UNSPEC as u8);
+        self.buf.push(0);
+        self.buf.extend_from_slice(&0u16.to_ne_bytes());
+        self.buf.extend_from_slice(&(index as i32).to_ne_bytes());
+        self.buf.extend_from_slice(&flags.to_ne_bytes());
+        self.buf.extend_from_slice(&change.to_ne_bytes());
+    }
+
+    fn attr(&mut self, ty: u16, data: &[u8]) {
+        let len = (4 + data.len()) as u16;
+        self.buf.extend_from_slice(&len.to_ne_bytes());
+        self.buf.extend_from_slice(&ty.to_ne_bytes());
+        self.buf.extend_from_slice(data);
+        self.pad();
+    }
+
+    fn attr_u32(&mut self, ty: u16, v: u32) {
+        self.attr(ty, &v.to_ne_bytes());
+    }
+
+    fn attr_str(&mut self, ty: u16, s: &str) {
+        let mut data = s.as_bytes().to_vec();
+        data.push(0);
+        self.attr(ty, &data);
+    }
+
+    /// Opens a nested attribute; its length is patched by `end_nested`.
+    fn begin_nested(&mut self, ty: u16) -> usize {
+        let start = self.buf.len();
+        self.buf.extend_from_slice(&0u16.to_ne_bytes());
+        self.buf.extend_from_slice(&ty.to_ne_bytes());
+        start
+    }
+
+    fn end_nested(&mut self, start: usize) {
+        let len = (self.buf.len() - start) as u16;
+        self.buf[start..start + 2].copy_from_slice(&len.to_ne_bytes());
+    }
+
+    fn pad(&mut self) {
+        self.buf.resize(align(self.buf.len()), 0);
+    }
+
+    fn finish(&mut self, seq: u32) -> &[u8] {
+        let len = self.buf.len() as u32;
+        self.buf[0..4].copy_from_slice(&len.to_ne_bytes());
+        self.buf[8..12].copy_from_slice(&seq.to_ne_bytes());
+        &self.buf
+    }
+}
+
+fn create_flags() -> u16 {
+    (libc::NLM_F_CREATE | libc::NLM_F_EXCL) as u16
+}
+
+fn ignore_exists(res: Result<()>) -> Result<()> {
+    match res {
+        Err(RuntimeError::Io(e)) if e.raw_os_error() == Some(libc::EEXIST) => Ok(()),
+        other => other,
+    }
+}
+
+fn align(len: usize) -> usize {
+    (len + 3) & !3
+}
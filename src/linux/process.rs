@@ -0,0 +1,175 @@
+use super::capabilities::{apply_sets, drop_bounding, set_keep_caps};
+use super::isolation::nix_to_io;
+use super::seccomp::{self, Filter};
+use super::userns::setgroups_denied;
+use crate::runtime::spec::{Capabilities, Rlimit, User};
+use crate::runtime::{Result, RuntimeError};
+use nix::sys::prctl;
+use nix::unistd::{Gid, Uid, chdir, setgid, setgroups, setuid};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Per-process attributes applied after `ContainerSetup`, right before exec:
+/// resource limits, OOM score, the seccomp filter, the container user,
+/// capabilities, working directory and no_new_privs.
+pub struct ProcessSetup<'a> {
+    rlimits: &'a [Rlimit],
+    oom_score_adj: Option<i32>,
+    no_new_privileges: bool,
+    seccomp: Option<&'a Filter>,
+    user: Option<&'a User>,
+    capabilities: Option<&'a Capabilities>,
+    cwd: Option<&'a Path>,
+}
+
+impl<'a> ProcessSetup<'a> {
+    pub fn new() -> Self {
+        Self {
+            rlimits: &[],
+            oom_score_adj: None,
+            no_new_privileges: false,
+            seccomp: None,
+            user: None,
+            capabilities: None,
+            cwd: None,
+        }
+    }
+
+    pub fn rlimits(mut self, rlimits: &'a [Rlimit]) -> Self {
+        self.rlimits = rlimits;
+        self
+    }
+
+    pub fn oom_score_adj(mut self, adj: Option<i32>) -> Self {
+        self.oom_score_adj = adj;
+        self
+    }
+
+    pub fn no_new_privileges(mut self, enabled: bool) -> Self {
+        self.no_new_privileges = enabled;
+        self
+    }
+
+    pub fn seccomp(mut self, filter: Option<&'a Filter>) -> Self {
+        self.seccomp = filter;
+        self
+    }
+
+    pub fn user(mut self, user: Option<&'a User>) -> Self {
+        self.user = user;
+        self
+    }
+
+    pub fn capabilities(mut self, capabilities: Option<&'a Capabilities>) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    pub fn cwd(mut self, cwd: Option<&'a Path>) -> Self {
+        self.cwd = cwd;
+        self
+    }
+
+    pub fn apply(self) -> Result<()> {
+        // raising hard limits needs CAP_SYS_RESOURCE, so before any drop
+        for rl in self.rlimits {
+            set_rlimit(rl)?;
+        }
+        // same for lowering it, with CAP_SYS_RESOURCE
+        if let Some(adj) = self.oom_score_adj {
+            fs::write("/proc/self/oom_score_adj", adj.to_string())
+                .map_err(|e| RuntimeError::Msg(format!("setting oom_score_adj to {adj}: {e}")))?;
+        }
+
+        if let Some(caps) = self.capabilities {
+            drop_bounding(caps)?;
+        }
+
+        // As late as it can go: without no_new_privs, installing it takes
+        // CAP_SYS_ADMIN, which switching to the container user and applying
+        // its sets give up. What runs after it (setuid, capset, the exec FIFO
+        // and execve) must be allowed by the profile.
+        if let Some(filter) = self.seccomp.filter(|_| !self.no_new_privileges) {
+            seccomp::install(filter)?;
+        }
+
+        if let Some(user) = self.user {
+            // without KEEPCAPS, setuid() away from root clears permitted
+            if self.capabilities.is_some() {
+                set_keep_caps(true)?;
+            }
+
+            let groups: Vec<Gid> = user
+                .additional_gids
+                .iter()
+                .map(|g| Gid::from_raw(*g))
+                .collect();
+            // rootless without newgidmap: nothing to drop, and not allowed
+            if !groups.is_empty() || !setgroups_denied() {
+                setgroups(&groups).map_err(nix_to_io)?;
+            }
+            setgid(Gid::from_raw(user.gid)).map_err(nix_to_io)?;
+            setuid(Uid::from_raw(user.uid)).map_err(nix_to_io)?;
+
+            if self.capabilities.is_some() {
+                set_keep_caps(false)?;
+            }
+        }
+
+        if let Some(caps) = self.capabilities {
+            apply_sets(caps)?;
+        }
+
+        if let Some(cwd) = self.cwd {
+            chdir(cwd).map_err(nix_to_io)?;
+        }
+
+        // with it, any process may install a filter, so that can wait until
+        // only the exec FIFO and execve are left for the profile to allow
+        if self.no_new_privileges {
+            prctl::set_no_new_privs().map_err(nix_to_io)?;
+            if let Some(filter) = self.seccomp {
+                seccomp::install(filter)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Maps an OCI rlimit name (`RLIMIT_NOFILE`) to its resource number.
+pub fn rlimit_resource(name: &str) -> Result<libc::__rlimit_resource_t> {
+    let res = match name.to_ascii_uppercase().as_str() {
+        "RLIMIT_AS" => libc::RLIMIT_AS,
+        "RLIMIT_CORE" => libc::RLIMIT_CORE,
+        "RLIMIT_CPU" => libc::RLIMIT_CPU,
+        "RLIMIT_DATA" => libc::RLIMIT_DATA,
+        "RLIMIT_FSIZE" => libc::RLIMIT_FSIZE,
+        "RLIMIT_LOCKS" => libc::RLIMIT_LOCKS,
+        "RLIMIT_MEMLOCK" => libc::RLIMIT_MEMLOCK,
+        "RLIMIT_MSGQUEUE" => libc::RLIMIT_MSGQUEUE,
+        "RLIMIT_NICE" => libc::RLIMIT_NICE,
+        "RLIMIT_NOFILE" => libc::RLIMIT_NOFILE,
+        "RLIMIT_NPROC" => libc::RLIMIT_NPROC,
+        "RLIMIT_RSS" => libc::RLIMIT_RSS,
+        "RLIMIT_RTPRIO" => libc::RLIMIT_RTPRIO,
+        "RLIMIT_RTTIME" => libc::RLIMIT_RTTIME,
+        "RLIMIT_SIGPENDING" => libc::RLIMIT_SIGPENDING,
+        "RLIMIT_STACK" => libc::RLIMIT_STACK,
+        _ => return Err(RuntimeError::Msg(format!("unknown rlimit: {name}"))),
+    };
+    Ok(res)
+}
+
+fn set_rlimit(rl: &Rlimit) -> Result<()> {
+    let limit = libc::rlimit {
+        rlim_cur: rl.soft,
+        rlim_max: rl.hard,
+    };
+    let rc = unsafe { libc::setrlimit(rlimit_resource(&rl.kind)?, &limit) };
+    if rc != 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    Ok(())
+}
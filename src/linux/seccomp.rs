@@ -0,0 +1,722 @@
+//! Seccomp filters compiled straight from a profile into classic BPF, no
+//! libseccomp. The program is flat: an architecture check, one block per
+//! syscall number trying that syscall's rules in profile order, and the
+//! default action for everything else.
+
+use super::syscalls::SYSCALLS;
+use crate::runtime::spec::{Capabilities, Seccomp, SyscallArg, SyscallCondition, SyscallRule};
+use crate::runtime::{Result, RuntimeError};
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Allows everything but what reaches past the container (kernel modules,
+/// kexec, the clock, keyrings) or undoes its setup (mount, unshare, setns);
+/// a capability that legitimately needs one of these brings it back.
+const DEFAULT_PROFILE: &str = include_str!("seccomp_default.json");
+
+/// The architecture podrun runs on, as the kernel, Go and libseccomp name it.
+struct Arch {
+    audit: u32,
+    go: &'static str,
+    scmp: &'static str,
+}
+
+const NATIVE: Option<Arch> = if cfg!(target_arch = "x86_64") {
+    Some(Arch {
+        audit: 0xc000_003e,
+        go: "amd64",
+        scmp: "SCMP_ARCH_X86_64",
+    })
+} else if cfg!(target_arch = "aarch64") {
+    Some(Arch {
+        audit: 0xc000_00b7,
+        go: "arm64",
+        scmp: "SCMP_ARCH_AARCH64",
+    })
+} else {
+    None
+};
+
+/// x32 syscalls come in under the x86_64 audit arch, with this bit set in nr.
+const X32_SYSCALL_BIT: u32 = 0x4000_0000;
+
+// struct seccomp_data { int nr; u32 arch; u64 instruction_pointer; u64 args[6]; }
+const NR: u32 = 0;
+const ARCH: u32 = 4;
+const ARGS: u32 = 16;
+
+// the few opcodes the filter needs
+const LD_W_ABS: u16 = 0x20;
+const ALU_AND_K: u16 = 0x54;
+const JMP_JEQ_K: u16 = 0x15;
+const JMP_JGT_K: u16 = 0x25;
+const JMP_JGE_K: u16 = 0x35;
+const RET_K: u16 = 0x06;
+
+/// A rule's argument conditions, and what the filter returns if they hold.
+type Rule<'a> = (&'a [SyscallArg], u32);
+
+/// A profile compiled for this machine and a given capability set.
+pub struct Filter {
+    insns: Vec<libc::sock_filter>,
+    flags: libc::c_ulong,
+}
+
+pub fn default_profile() -> Seccomp {
+    serde_json::from_str(DEFAULT_PROFILE).expect("built-in seccomp profile is valid")
+}
+
+/// Reads a profile in Docker's or OCI's `linux.seccomp` format.
+pub fn load_profile(path: &Path) -> Result<Seccomp> {
+    let raw = fs::read_to_string(path)
+        .map_err(|e| RuntimeError::Msg(format!("reading {}: {e}", path.display())))?;
+    serde_json::from_str(&raw).map_err(|e| RuntimeError::Msg(format!("{}: {e}", path.display())))
+}
+
+/// Compiles `profile` for a process with `caps`, which decide Docker's
+/// capability conditions; `None` counts as having every capability.
+pub fn compile(profile: &Seccomp, caps: Option<&Capabilities>) -> Result<Filter> {
+    let arch = native()?;
+    let kernel = kernel_version();
+
+    // the rules for each syscall number, in the order the profile has them
+    let mut blocks: Vec<(u32, Vec<Rule>)> = vec![];
+    for rule in &profile.syscalls {
+        if !applies(rule, caps, arch.go, kernel) {
+            continue;
+        }
+        let ret = action(&rule.action, rule.errno_ret)?;
+
+        // a profile written for several architectures names syscalls this
+        // one doesn't have
+        for nr in rule.names.iter().filter_map(|n| syscall_nr(n)) {
+            let rules = match blocks.iter_mut().find(|(n, _)| *n == nr) {
+                Some((_, rules)) => rules,
+                None => {
+                    blocks.push((nr, vec![]));
+                    &mut blocks.last_mut().expect("just pushed").1
+                }
+            };
+            // nothing after an unconditional rule is ever reached
+            if rules.last().is_none_or(|(args, _)| !args.is_empty()) {
+                rules.push((&rule.args, ret));
+            }
+        }
+    }
+
+    let mut insns = arch_check(&arch);
+    for (nr, rules) in &blocks {
+        insns.extend(syscall_block(*nr, rules)?);
+    }
+    insns.push(stmt(
+        RET_K,
+        action(&profile.default_action, profile.default_errno_ret)?,
+    ));
+
+    if insns.len() > libc::BPF_MAXINSNS as usize {
+        return Err(RuntimeError::Msg(format!(
+            "seccomp profile compiles to {} instructions, the kernel takes at most {}",
+            insns.len(),
+            libc::BPF_MAXINSNS
+        )));
+    }
+
+    Ok(Filter {
+        insns,
+        flags: filter_flags(&profile.flags)?,
+    })
+}
+
+/// A filter that decides nothing itself but hands every syscall to a
+/// listener, for `run --seccomp-learn`; see [`super::seccomp_learn`].
+pub fn learning_filter() -> Result<Filter> {
+    let mut insns = arch_check(&native()?);
+    insns.push(stmt(RET_K, libc::SECCOMP_RET_USER_NOTIF));
+    Ok(Filter {
+        insns,
+        flags: libc::SECCOMP_FILTER_FLAG_NEW_LISTENER,
+    })
+}
+
+/// A profile allowing exactly `syscalls` and failing everything else with
+/// EPERM, in the format `--seccomp-profile` reads.
+pub fn allow_list(syscalls: &BTreeSet<u32>) -> Result<Seccomp> {
+    let arch = native()?;
+    let mut names = Vec::new();
+    let mut unknown = Vec::new();
+    for &nr in syscalls {
+        match SYSCALLS.iter().find(|(_, n)| *n == nr) {
+            Some((name, _)) => names.push(name.to_string()),
+            None => unknown.push(nr.to_string()),
+        }
+    }
+    names.sort();
+
+    // profiles name their syscalls, so these can't be allowed
+    if !unknown.is_empty() {
+        eprintln!(
+            "warning: syscall number(s) {} have no name in podrun's table and are left out \
+             of the profile; the container will get EPERM for them",
+            unknown.join(", ")
+        );
+    }
+
+    Ok(Seccomp {
+        default_action: "SCMP_ACT_ERRNO".into(),
+        default_errno_ret: Some(libc::EPERM as u32),
+        architectures: vec![arch.scmp.into()],
+        arch_map: vec![],
+        flags: vec![],
+        syscalls: vec![SyscallRule {
+            names,
+            action: "SCMP_ACT_ALLOW".into(),
+            errno_ret: None,
+            args: vec![],
+            includes: SyscallCondition::default(),
+            excludes: SyscallCondition::default(),
+            comment: None,
+        }],
+    })
+}
+
+/// Installs `filter` for the calling process and everything it execs. Needs
+/// CAP_SYS_ADMIN in its user namespace unless no_new_privs is set.
+pub fn install(filter: &Filter) -> Result<()> {
+    let prog = libc::sock_fprog {
+        len: filter.insns.len() as u16,
+        filter: filter.insns.as_ptr() as *mut libc::sock_filter,
+    };
+    // the kernel copies the program before returning
+    let rc = unsafe {
+        libc::syscall(
+            libc::SYS_seccomp,
+            libc::SECCOMP_SET_MODE_FILTER,
+            filter.flags,
+            &prog as *const libc::sock_fprog,
+        )
+    };
+    // a learning filter returns its listener, which stays open until exec
+    if rc < 0 {
+        return Err(RuntimeError::Msg(format!(
+            "installing the seccomp filter: {}",
+            io::Error::last_os_error()
+        )));
+    }
+    Ok(())
+}
+
+fn native() -> Result<Arch> {
+    NATIVE.ok_or_else(|| RuntimeError::Msg("seccomp is not supported on this architecture".into()))
+}
+
+/// Kills anything coming in through another ABI than the native one, whose
+/// syscall numbers would mean something else, then loads the number.
+fn arch_check(arch: &Arch) -> Vec<libc::sock_filter> {
+    let mut insns = vec![
+        stmt(LD_W_ABS, ARCH),
+        jump(JMP_JEQ_K, arch.audit, 1, 0),
+        stmt(RET_K, libc::SECCOMP_RET_KILL_PROCESS),
+        stmt(LD_W_ABS, NR),
+    ];
+    if cfg!(target_arch = "x86_64") {
+        insns.push(jump(JMP_JGE_K, X32_SYSCALL_BIT, 0, 1));
+        insns.push(stmt(RET_K, libc::SECCOMP_RET_KILL_PROCESS));
+    }
+    insns
+}
+
+fn syscall_nr(name: &str) -> Option<u32> {
+    SYSCALLS.iter().find(|(n, _)| *n == name).map(|&(_, nr)| nr)
+}
+
+/// Tries `rules` on syscall `nr` in order. Falls through to the next block,
+/// with the syscall number loaded again, if none of them matches.
+fn syscall_block(nr: u32, rules: &[Rule]) -> Result<Vec<libc::sock_filter>> {
+    let mut body = vec![];
+    for (args, ret) in rules {
+        let mut fails = vec![];
+        for arg in *args {
+            compare(arg, &mut body, &mut fails)?;
+        }
+        body.push(stmt(RET_K, *ret));
+
+        // a failed comparison skips the rest of this rule
+        let end = body.len();
+        for (i, on_true) in fails {
+            let off = jump_offset(end - i - 1, nr)?;
+            if on_true {
+                body[i].jt = off;
+            } else {
+                body[i].jf = off;
+            }
+        }
+    }
+    // comparisons leave an argument in A
+    if rules.iter().any(|(args, _)| !args.is_empty()) {
+        body.push(stmt(LD_W_ABS, NR));
+    }
+
+    let mut block = vec![jump(JMP_JEQ_K, nr, 0, jump_offset(body.len(), nr)?)];
+    block.extend(body);
+    Ok(block)
+}
+
+fn jump_offset(off: usize, nr: u32) -> Result<u8> {
+    u8::try_from(off).map_err(|_| {
+        RuntimeError::Msg(format!(
+            "seccomp profile has too many conditions for syscall {nr}"
+        ))
+    })
+}
+
+/// Appends a 64-bit comparison, done as two 32-bit ones (high word first)
+/// since classic BPF only loads words. Jumps that should leave the rule are
+/// recorded in `fails` as (index, whether it's the true branch).
+fn compare(
+    arg: &SyscallArg,
+    out: &mut Vec<libc::sock_filter>,
+    fails: &mut Vec<(usize, bool)>,
+) -> Result<()> {
+    if arg.index > 5 {
+        return Err(RuntimeError::Msg(format!(
+            "seccomp argument index {} out of range, syscalls have 6",
+            arg.index
+        )));
+    }
+    // little-endian: the low word comes first
+    let lo = ARGS + 8 * arg.index;
+    let hi = lo + 4;
+    let (v_hi, v_lo) = ((arg.value >> 32) as u32, arg.value as u32);
+
+    let mut fail_if = |out: &mut Vec<libc::sock_filter>, code, k, on_true| {
+        fails.push((out.len(), on_true));
+        out.push(jump(code, k, 0, 0));
+    };
+
+    match arg.op.as_str() {
+        "SCMP_CMP_EQ" => {
+            out.push(stmt(LD_W_ABS, hi));
+            fail_if(out, JMP_JEQ_K, v_hi, false);
+            out.push(stmt(LD_W_ABS, lo));
+            fail_if(out, JMP_JEQ_K, v_lo, false);
+        }
+        "SCMP_CMP_NE" => {
+            out.push(stmt(LD_W_ABS, hi));
+            out.push(jump(JMP_JEQ_K, v_hi, 0, 2));
+            out.push(stmt(LD_W_ABS, lo));
+            fail_if(out, JMP_JEQ_K, v_lo, true);
+        }
+        "SCMP_CMP_MASKED_EQ" => {
+            let (want_hi, want_lo) = ((arg.value_two >> 32) as u32, arg.value_two as u32);
+            out.push(stmt(LD_W_ABS, hi));
+            out.push(stmt(ALU_AND_K, v_hi));
+            fail_if(out, JMP_JEQ_K, want_hi, false);
+            out.push(stmt(LD_W_ABS, lo));
+            out.push(stmt(ALU_AND_K, v_lo));
+            fail_if(out, JMP_JEQ_K, want_lo, false);
+        }
+        op @ ("SCMP_CMP_GT" | "SCMP_CMP_GE") => {
+            // a greater high word decides alone, an equal one defers to the low
+            out.push(stmt(LD_W_ABS, hi));
+            out.push(jump(JMP_JGT_K, v_hi, 3, 0));
+            fail_if(out, JMP_JEQ_K, v_hi, false);
+            out.push(stmt(LD_W_ABS, lo));
+            let code = if op == "SCMP_CMP_GT" {
+                JMP_JGT_K
+            } else {
+                JMP_JGE_K
+            };
+            fail_if(out, code, v_lo, false);
+        }
+        op @ ("SCMP_CMP_LT" | "SCMP_CMP_LE") => {
+            out.push(stmt(LD_W_ABS, hi));
+            fail_if(out, JMP_JGT_K, v_hi, true);
+            out.push(jump(JMP_JEQ_K, v_hi, 0, 2));
+            out.push(stmt(LD_W_ABS, lo));
+            let code = if op == "SCMP_CMP_LT" {
+                JMP_JGE_K
+            } else {
+                JMP_JGT_K
+            };
+            fail_if(out, code, v_lo, true);
+        }
+        other => {
+            return Err(RuntimeError::Msg(format!(
+                "unsupported seccomp operator {other}"
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// The filter's return value for a libseccomp action name.
+fn action(name: &str, errno: Option<u32>) -> Result<u32> {
+    let ret = match name {
+        "SCMP_ACT_ALLOW" => libc::SECCOMP_RET_ALLOW,
+        "SCMP_ACT_ERRNO" => {
+            libc::SECCOMP_RET_ERRNO | (errno.unwrap_or(libc::EPERM as u32) & libc::SECCOMP_RET_DATA)
+        }
+        "SCMP_ACT_KILL" | "SCMP_ACT_KILL_THREAD" => libc::SECCOMP_RET_KILL_THREAD,
+        "SCMP_ACT_KILL_PROCESS" => libc::SECCOMP_RET_KILL_PROCESS,
+        "SCMP_ACT_TRAP" => libc::SECCOMP_RET_TRAP,
+        "SCMP_ACT_TRACE" => libc::SECCOMP_RET_TRACE | (errno.unwrap_or(0) & libc::SECCOMP_RET_DATA),
+        "SCMP_ACT_LOG" => libc::SECCOMP_RET_LOG,
+        other => {
+            return Err(RuntimeError::Msg(format!(
+                "unsupported seccomp action {other}"
+            )));
+        }
+    };
+    Ok(ret)
+}
+
+fn filter_flags(names: &[String]) -> Result<libc::c_ulong> {
+    names.iter().try_fold(0, |flags, name| {
+        let flag = match name.as_str() {
+            "SECCOMP_FILTER_FLAG_TSYNC" => libc::SECCOMP_FILTER_FLAG_TSYNC,
+            "SECCOMP_FILTER_FLAG_LOG" => libc::SECCOMP_FILTER_FLAG_LOG,
+            "SECCOMP_FILTER_FLAG_SPEC_ALLOW" => libc::SECCOMP_FILTER_FLAG_SPEC_ALLOW,
+            other => {
+                return Err(RuntimeError::Msg(format!(
+                    "unsupported seccomp flag {other}"
+                )));
+            }
+        };
+        Ok(flags | flag)
+    })
+}
+
+/// Docker's `includes`/`excludes`: a rule is only used if the container has
+/// all the included capabilities and none of the excluded ones, and
+/// likewise for architecture and kernel version.
+fn applies(
+    rule: &SyscallRule,
+    caps: Option<&Capabilities>,
+    arch: &str,
+    kernel: (u32, u32),
+) -> bool {
+    let has_cap = |name: &String| {
+        caps.is_none_or(|c| c.bounding.iter().any(|b| b.eq_ignore_ascii_case(name)))
+    };
+    let matches = |cond: &SyscallCondition, all_caps: bool| {
+        let caps = if all_caps {
+            cond.caps.iter().all(has_cap)
+        } else {
+            cond.caps.iter().any(has_cap)
+        };
+        let arch = cond.arches.iter().any(|a| a == arch);
+        let kernel = cond
+            .min_kernel
+            .as_deref()
+            .is_some_and(|v| kernel >= parse_version(v));
+        (caps, arch, kernel)
+    };
+
+    let inc = &rule.includes;
+    let (inc_caps, inc_arch, inc_kernel) = matches(inc, true);
+    let (exc_caps, exc_arch, exc_kernel) = matches(&rule.excludes, false);
+
+    inc_caps
+        && (inc.arches.is_empty() || inc_arch)
+        && (inc.min_kernel.is_none() || inc_kernel)
+        && !exc_caps
+        && !exc_arch
+        && !exc_kernel
+}
+
+fn kernel_version() -> (u32, u32) {
+    fs::read_to_string("/proc/sys/kernel/osrelease")
+        .map(|v| parse_version(&v))
+        .unwrap_or_default()
+}
+
+/// `6.1.0-13-amd64` -> (6, 1)
+fn parse_version(v: &str) -> (u32, u32) {
+    let mut parts = v.trim().split(|c: char| !c.is_ascii_digit());
+    let mut next = || parts.next().and_then(|p| p.parse().ok()).unwrap_or(0);
+    (next(), next())
+}
+
+fn stmt(code: u16, k: u32) -> libc::sock_filter {
+    jump(code, k, 0, 0)
+}
+
+fn jump(code: u16, k: u32, jt: u8, jf: u8) -> libc::sock_filter {
+    libc::sock_filter { code, jt, jf, k }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linux::capabilities;
+    use serde_json::json;
+
+    const EPERM: u32 = libc::SECCOMP_RET_ERRNO | libc::EPERM as u32;
+    const CLONE_NEWNS: u64 = 0x0002_0000;
+    const CLONE_NEWUSER: u64 = 0x1000_0000;
+    const SIGCHLD: u64 = 17;
+
+    fn arch() -> u32 {
+        native().expect("native arch").audit
+    }
+
+    fn nr(name: &str) -> u32 {
+        syscall_nr(name).expect("known syscall")
+    }
+
+    /// Runs `filter` the way the kernel would on one `seccomp_data`, covering
+    /// the instructions `compile` emits.
+    fn run(filter: &Filter, arch: u32, nr: u32, args: [u64; 6]) -> u32 {
+        let mut data = [0u8; 64];
+        data[0..4].copy_from_slice(&nr.to_ne_bytes());
+        data[4..8].copy_from_slice(&arch.to_ne_bytes());
+        for (i, arg) in args.iter().enumerate() {
+            let at = ARGS as usize + 8 * i;
+            data[at..at + 8].copy_from_slice(&arg.to_ne_bytes());
+        }
+
+        let (mut a, mut pc) = (0u32, 0usize);
+        loop {
+            let ins = filter.insns[pc];
+            pc += 1;
+            let k = ins.k;
+            let taken = match ins.code {
+                LD_W_ABS => {
+                    let at = k as usize;
+                    a = u32::from_ne_bytes(data[at..at + 4].try_into().unwrap());
+                    continue;
+                }
+                ALU_AND_K => {
+                    a &= k;
+                    continue;
+                }
+                RET_K => return k,
+                JMP_JEQ_K => a == k,
+                JMP_JGT_K => a > k,
+                JMP_JGE_K => a >= k,
+                other => panic!("unexpected opcode {other:#x}"),
+            };
+            pc += if taken { ins.jt } else { ins.jf } as usize;
+        }
+    }
+
+    fn profile(value: serde_json::Value) -> Seccomp {
+        serde_json::from_value(value).expect("valid profile")
+    }
+
+    /// Allows getpid only if `arg0 <op> value` (and value_two, for masks).
+    fn one_comparison(op: &str, value: u64, value_two: u64) -> Filter {
+        let p = profile(json!({
+            "defaultAction": "SCMP_ACT_ERRNO",
+            "syscalls": [{
+                "names": ["getpid"],
+                "action": "SCMP_ACT_ALLOW",
+                "args": [{ "index": 0, "value": value, "valueTwo": value_two, "op": op }]
+            }]
+        }));
+        compile(&p, None).expect("compiles")
+    }
+
+    #[test]
+    fn kills_foreign_architectures() {
+        let filter = compile(&default_profile(), None).unwrap();
+        assert_eq!(
+            run(&filter, 0x4000_0003, nr("getpid"), [0; 6]),
+            libc::SECCOMP_RET_KILL_PROCESS
+        );
+        assert_eq!(
+            run(&filter, arch(), nr("getpid"), [0; 6]),
+            libc::SECCOMP_RET_ALLOW
+        );
+    }
+
+    #[test]
+    #[cfg(target_arch = "x86_64")]
+    fn kills_x32_syscalls() {
+        let filter = compile(&default_profile(), None).unwrap();
+        assert_eq!(
+            run(&filter, arch(), X32_SYSCALL_BIT | nr("getpid"), [0; 6]),
+            libc::SECCOMP_RET_KILL_PROCESS
+        );
+    }
+
+    #[test]
+    fn default_profile_only_allows_clone_without_namespaces() {
+        let caps = capabilities::default_set();
+        let filter = compile(&default_profile(), Some(&caps)).unwrap();
+        let clone = |flags| run(&filter, arch(), nr("clone"), [flags, 0, 0, 0, 0, 0]);
+
+        assert_eq!(clone(SIGCHLD), libc::SECCOMP_RET_ALLOW);
+        assert_eq!(clone(SIGCHLD | CLONE_NEWNS), EPERM);
+        assert_eq!(clone(CLONE_NEWUSER), EPERM);
+        // the mask covers the low word only; high bits don't sneak past it
+        assert_eq!(clone(SIGCHLD | 1 << 40), libc::SECCOMP_RET_ALLOW);
+        assert_eq!(
+            run(&filter, arch(), nr("clone3"), [0; 6]),
+            libc::SECCOMP_RET_ERRNO | libc::ENOSYS as u32
+        );
+        assert_eq!(run(&filter, arch(), nr("mount"), [0; 6]), EPERM);
+        assert_eq!(
+            run(&filter, arch(), nr("getpid"), [0; 6]),
+            libc::SECCOMP_RET_ALLOW
+        );
+    }
+
+    #[test]
+    fn sys_admin_lifts_the_namespace_rules() {
+        let mut caps = capabilities::default_set();
+        capabilities::adjust(&mut caps, &["SYS_ADMIN".into()], &[]).unwrap();
+        let filter = compile(&default_profile(), Some(&caps)).unwrap();
+
+        let clone = run(&filter, arch(), nr("clone"), [CLONE_NEWNS, 0, 0, 0, 0, 0]);
+        assert_eq!(clone, libc::SECCOMP_RET_ALLOW);
+        assert_eq!(
+            run(&filter, arch(), nr("mount"), [0; 6]),
+            libc::SECCOMP_RET_ALLOW
+        );
+        // other capabilities' rules stay
+        assert_eq!(run(&filter, arch(), nr("kexec_load"), [0; 6]), EPERM);
+    }
+
+    /// Checks `op` against plain u64 arithmetic, on arguments pairing every
+    /// high word with every low word around the value's own. The value's
+    /// words are tried both ways round, so a comparison that ends up
+    /// against the wrong word shows.
+    fn check_comparison(op: &str, holds: fn(u64, u64) -> bool) {
+        let words = [0, 4, 5, 6, 7, 8, u32::MAX as u64];
+        for value in [0x0000_0005_0000_0007, 0x0000_0007_0000_0005] {
+            let filter = one_comparison(op, value, 0);
+            for hi in words {
+                for lo in words {
+                    let arg = hi << 32 | lo;
+                    let want = if holds(arg, value) {
+                        libc::SECCOMP_RET_ALLOW
+                    } else {
+                        EPERM
+                    };
+                    let got = run(&filter, arch(), nr("getpid"), [arg, 0, 0, 0, 0, 0]);
+                    assert_eq!(got, want, "{arg:#x} {op} {value:#x}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn eq() {
+        check_comparison("SCMP_CMP_EQ", |a, b| a == b);
+    }
+
+    #[test]
+    fn ne() {
+        check_comparison("SCMP_CMP_NE", |a, b| a != b);
+    }
+
+    #[test]
+    fn gt() {
+        check_comparison("SCMP_CMP_GT", |a, b| a > b);
+    }
+
+    #[test]
+    fn ge() {
+        check_comparison("SCMP_CMP_GE", |a, b| a >= b);
+    }
+
+    #[test]
+    fn lt() {
+        check_comparison("SCMP_CMP_LT", |a, b| a < b);
+    }
+
+    #[test]
+    fn le() {
+        check_comparison("SCMP_CMP_LE", |a, b| a <= b);
+    }
+
+    #[test]
+    fn masked_eq() {
+        let (mask, want) = (0x0000_00f0_0000_000f, 0x0000_0010_0000_0003);
+        let filter = one_comparison("SCMP_CMP_MASKED_EQ", mask, want);
+        for arg in [
+            0x0000_0010_0000_0003u64,
+            0xffff_ff1f_ffff_fff3,
+            0x0000_0020_0000_0003,
+            0x0000_0010_0000_0004,
+            0,
+        ] {
+            let expect = if arg & mask == want {
+                libc::SECCOMP_RET_ALLOW
+            } else {
+                EPERM
+            };
+            let got = run(&filter, arch(), nr("getpid"), [arg, 0, 0, 0, 0, 0]);
+            assert_eq!(got, expect, "{arg:#x} & {mask:#x} == {want:#x}");
+        }
+    }
+
+    #[test]
+    fn all_conditions_of_a_rule_must_hold() {
+        let p = profile(json!({
+            "defaultAction": "SCMP_ACT_ERRNO",
+            "syscalls": [
+                {
+                    "names": ["getpid"],
+                    "action": "SCMP_ACT_ALLOW",
+                    "args": [
+                        { "index": 0, "value": 1, "op": "SCMP_CMP_EQ" },
+                        { "index": 2, "value": 10, "op": "SCMP_CMP_GT" }
+                    ]
+                },
+                {
+                    "names": ["getpid"],
+                    "action": "SCMP_ACT_ERRNO",
+                    "errnoRet": 13,
+                    "args": [{ "index": 1, "value": 7, "op": "SCMP_CMP_EQ" }]
+                },
+                {
+                    "names": ["getppid"],
+                    "action": "SCMP_ACT_ALLOW"
+                }
+            ]
+        }));
+        let filter = compile(&p, None).unwrap();
+        let getpid = |args| run(&filter, arch(), nr("getpid"), args);
+
+        assert_eq!(getpid([1, 0, 11, 0, 0, 0]), libc::SECCOMP_RET_ALLOW);
+        assert_eq!(getpid([1, 0, 10, 0, 0, 0]), EPERM);
+        assert_eq!(getpid([2, 0, 11, 0, 0, 0]), EPERM);
+        // the first rule failing falls through to the second
+        assert_eq!(getpid([2, 7, 11, 0, 0, 0]), libc::SECCOMP_RET_ERRNO | 13);
+        // failing both reloads the syscall number before the blocks after
+        // it, rather than comparing them to the argument left over
+        let leftover = nr("getppid") as u64;
+        assert_eq!(getpid([2, leftover, 11, 0, 0, 0]), EPERM);
+        assert_eq!(
+            run(&filter, arch(), nr("getppid"), [0; 6]),
+            libc::SECCOMP_RET_ALLOW
+        );
+    }
+
+    #[test]
+    fn syscalls_since_the_unified_numbering_all_have_names() {
+        // from 424 on, every architecture shares one contiguous numbering
+        let highest = SYSCALLS.iter().map(|(_, n)| *n).max().unwrap();
+        for nr in 424..=highest {
+            assert!(
+                SYSCALLS.iter().any(|(_, n)| *n == nr),
+                "syscall {nr} has no name"
+            );
+        }
+    }
+
+    #[test]
+    fn allow_list_allows_exactly_the_learned_syscalls() {
+        let learned = BTreeSet::from([nr("getpid"), nr("cachestat")]);
+        let filter = compile(&allow_list(&learned).unwrap(), None).unwrap();
+        let result = |nr| run(&filter, arch(), nr, [0; 6]);
+
+        assert_eq!(result(nr("getpid")), libc::SECCOMP_RET_ALLOW);
+        assert_eq!(result(nr("cachestat")), libc::SECCOMP_RET_ALLOW);
+        assert_eq!(result(nr("getppid")), EPERM);
+    }
+}
@@ -0,0 +1,213 @@
+//! `run --seccomp-learn`: init installs [`seccomp::learning_filter`], which
+//! hands every syscall to a listener instead of deciding. A helper forked by
+//! the supervisor takes that listener out of init, notes each syscall and
+//! lets it go through, and once init is gone writes what it saw as an
+//! allow-list.
+
+use super::seccomp;
+use crate::linux::isolation::nix_to_io;
+use crate::runtime::Result;
+use crate::runtime::RuntimeError;
+use nix::fcntl::OFlag;
+use nix::sys::signal::{self, Signal};
+use nix::sys::wait::waitpid;
+use nix::unistd::{ForkResult, Pid, fork, pipe2};
+use std::collections::BTreeSet;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::path::Path;
+
+// _IOWR('!', 0, struct seccomp_notif) and _IOWR('!', 1, struct seccomp_notif_resp)
+const SECCOMP_IOCTL_NOTIF_RECV: libc::c_ulong = 0xc050_2100;
+const SECCOMP_IOCTL_NOTIF_SEND: libc::c_ulong = 0xc018_2101;
+
+/// Records the syscalls of init and its children in a process of its own,
+/// so the supervisor stays single-threaded for forking init.
+pub struct Learner {
+    helper: Pid,
+    /// Carries init's pid to the helper; closing it tells the helper to stop.
+    control: File,
+}
+
+impl Learner {
+    /// Forks the helper, idle until [`Learner::watch`] names init. Has to
+    /// happen before the supervisor unshares its PID namespace, or the
+    /// helper would become that namespace's init in place of the container's.
+    pub fn start(out: &Path) -> Result<Self> {
+        let (rx, tx) = pipe2(OFlag::O_CLOEXEC).map_err(nix_to_io)?;
+
+        match unsafe { fork() }.map_err(nix_to_io)? {
+            ForkResult::Child => {
+                drop(tx);
+                let code = match learn(File::from(rx), out) {
+                    Ok(()) => 0,
+                    Err(e) => {
+                        eprintln!("warning: seccomp learning: {e}");
+                        1
+                    }
+                };
+                unsafe { libc::_exit(code) }
+            }
+            ForkResult::Parent { child } => Ok(Self {
+                helper: child,
+                control: File::from(tx),
+            }),
+        }
+    }
+
+    /// Starts listening to `pid`, a just forked init that will install the
+    /// learning filter. Has to happen before waiting on init for anything:
+    /// from the filter on, init can't make a syscall that isn't answered.
+    pub fn watch(&self, pid: i32) {
+        let _ = (&self.control).write_all(&pid.to_ne_bytes());
+    }
+
+    /// Stops recording, with init reaped, and waits for the helper to write
+    /// the profile. It reports its own errors.
+    pub fn finish(self) {
+        drop(self.control);
+        // ECHILD if the helper was done early and got reaped along with init
+        let _ = waitpid(self.helper, None);
+    }
+}
+
+fn learn(mut control: File, out: &Path) -> Result<()> {
+    close_inherited(control.as_raw_fd());
+
+    let mut pid = [0u8; 4];
+    if control.read_exact(&mut pid).is_err() {
+        // init was never forked
+        return Ok(());
+    }
+    let syscalls = record(i32::from_ne_bytes(pid), &control)?;
+
+    let profile = seccomp::allow_list(&syscalls)?;
+    let json =
+        serde_json::to_string_pretty(&profile).map_err(|e| RuntimeError::Msg(e.to_string()))?;
+    fs::write(out, json + "\n")
+        .map_err(|e| RuntimeError::Msg(format!("writing {}: {e}", out.display())))?;
+    Ok(())
+}
+
+/// Closes everything but stdio and `keep` that the helper got from the
+/// supervisor: the exec FIFO, the report pipe, init's pty and the like must
+/// not outlive init because the helper is still around.
+fn close_inherited(keep: i32) {
+    unsafe {
+        if keep > 3 {
+            libc::syscall(libc::SYS_close_range, 3, keep - 1, 0);
+        }
+        libc::syscall(libc::SYS_close_range, keep + 1, u32::MAX, 0);
+    }
+}
+
+/// Whether the supervisor closed `control`, waiting up to `timeout` ms.
+fn stopped(control: &File, timeout: i32) -> bool {
+    let mut pfd = libc::pollfd {
+        fd: control.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    unsafe { libc::poll(&mut pfd, 1, timeout) > 0 }
+}
+
+fn record(pid: i32, control: &File) -> Result<BTreeSet<u32>> {
+    let mut seen = BTreeSet::new();
+    let listener = match take_listener(pid, control) {
+        Ok(Some(listener)) => listener,
+        Ok(None) => return Ok(seen),
+        Err(e) => {
+            // rather than leave it stuck on its next syscall forever
+            let _ = signal::kill(Pid::from_raw(pid), Signal::SIGKILL);
+            return Err(e);
+        }
+    };
+
+    loop {
+        let mut pfds = [
+            libc::pollfd {
+                fd: listener.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            },
+            // the supervisor is done, even if a daemonized child sits idle
+            libc::pollfd {
+                fd: control.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            },
+        ];
+        match unsafe { libc::poll(pfds.as_mut_ptr(), 2, -1) } {
+            -1 if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted => continue,
+            -1 => return Err(io::Error::last_os_error().into()),
+            _ => {}
+        }
+        // nothing is left running under the filter
+        if pfds[1].revents != 0 || pfds[0].revents & libc::POLLHUP != 0 {
+            break;
+        }
+
+        // the kernel wants the request zeroed
+        let mut req: libc::seccomp_notif = unsafe { std::mem::zeroed() };
+        if unsafe { libc::ioctl(listener.as_raw_fd(), SECCOMP_IOCTL_NOTIF_RECV, &mut req) } < 0 {
+            // ENOENT: the caller was killed in the meantime
+            continue;
+        }
+        seen.insert(req.data.nr as u32);
+
+        let resp = libc::seccomp_notif_resp {
+            id: req.id,
+            val: 0,
+            error: 0,
+            flags: libc::SECCOMP_USER_NOTIF_FLAG_CONTINUE as u32,
+        };
+        // same ENOENT; nothing to do about it either way
+        unsafe { libc::ioctl(listener.as_raw_fd(), SECCOMP_IOCTL_NOTIF_SEND, &resp) };
+    }
+    Ok(seen)
+}
+
+/// Waits for init to install the filter and copies the listener out of it.
+/// Init blocks on its very next syscall until that is answered, so it can't
+/// exec (closing the listener) before this finds it. `None` if init died
+/// first, e.g. of a failed setup.
+fn take_listener(pid: i32, control: &File) -> Result<Option<OwnedFd>> {
+    let pidfd = unsafe { libc::syscall(libc::SYS_pidfd_open, pid, 0) };
+    if pidfd < 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    // the kernel just handed us this fd
+    let pidfd = unsafe { OwnedFd::from_raw_fd(pidfd as i32) };
+
+    let fd_dir = format!("/proc/{pid}/fd");
+    loop {
+        let Ok(entries) = fs::read_dir(&fd_dir) else {
+            return Ok(None);
+        };
+        for entry in entries.flatten() {
+            let is_listener = fs::read_link(entry.path())
+                .is_ok_and(|target| target == Path::new("anon_inode:seccomp notify"));
+            let target_fd = entry
+                .file_name()
+                .to_str()
+                .and_then(|n| n.parse::<i32>().ok());
+            let (true, Some(target_fd)) = (is_listener, target_fd) else {
+                continue;
+            };
+
+            let fd =
+                unsafe { libc::syscall(libc::SYS_pidfd_getfd, pidfd.as_raw_fd(), target_fd, 0) };
+            if fd < 0 {
+                return Err(RuntimeError::Msg(format!(
+                    "taking the seccomp listener from init: {}",
+                    io::Error::last_os_error()
+                )));
+            }
+            return Ok(Some(unsafe { OwnedFd::from_raw_fd(fd as i32) }));
+        }
+        if stopped(control, 1) {
+            return Ok(None);
+        }
+    }
+}
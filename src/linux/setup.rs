@@ -1,17 +1,48 @@
+use super::cgroup::Cgroup;
+use super::devices::{create_default_devices, create_devices, mount_dev_filesystems};
+use super::mounts::{
+    Overlay, make_readonly, mask_path, mount_in_rootfs, mount_tmpfs, remount_readonly,
+};
+use super::userns::in_user_namespace;
 use crate::runtime::Result;
-use std::path::Path;
+use crate::runtime::spec::{Device, Mount, Tmpfs};
+use std::path::{Path, PathBuf};
 
 use super::isolation::{
-    chdir_to_container_root, detach_and_remove_old_root, ensure_rootfs_is_mountpoint,
-    enter_mount_and_uts_namespaces, make_mounts_private, mount_minimal_dev, mount_proc,
-    pivot_root_into, set_container_hostname,
+    bring_up_loopback, chdir_to_container_root, detach_and_remove_old_root,
+    ensure_rootfs_is_mountpoint, enter_cgroup_namespace, enter_ipc_namespace,
+    enter_mount_and_uts_namespaces, enter_network_namespace, join_network_namespace,
+    make_mounts_private, mount_proc, mount_sys, pivot_root_into, set_container_hostname,
 };
 
+/// Where the container's /dev comes from. Device nodes are only created on
+/// a fresh mount, never in the image's own directory.
+enum Dev<'a> {
+    Ours,
+    Tmpfs(&'a Tmpfs),
+    /// A spec mount, in place since before `pivot_root`
+    Mounted,
+    Image,
+}
+
 pub struct ContainerSetup<'a> {
     rootfs: &'a Path,
     hostname: Option<&'a str>,
     mount_proc: bool,
+    mount_sys: bool,
     mount_dev: bool,
+    network_namespace: bool,
+    network_path: Option<&'a Path>,
+    ipc_namespace: bool,
+    cgroup_namespace: bool,
+    cgroup: Option<&'a Cgroup>,
+    mounts: &'a [Mount],
+    tmpfs: &'a [Tmpfs],
+    devices: &'a [Device],
+    masked_paths: &'a [PathBuf],
+    readonly_paths: &'a [PathBuf],
+    overlay: Option<&'a Overlay>,
+    readonly_rootfs: bool,
 }
 
 impl<'a> ContainerSetup<'a> {
@@ -20,7 +51,20 @@ impl<'a> ContainerSetup<'a> {
             rootfs,
             hostname: None,
             mount_proc: true,
+            mount_sys: true,
             mount_dev: true,
+            network_namespace: true,
+            network_path: None,
+            ipc_namespace: false,
+            cgroup_namespace: false,
+            cgroup: None,
+            mounts: &[],
+            tmpfs: &[],
+            devices: &[],
+            masked_paths: &[],
+            readonly_paths: &[],
+            overlay: None,
+            readonly_rootfs: false,
         }
     }
 
@@ -34,19 +78,145 @@ impl<'a> ContainerSetup<'a> {
         self
     }
 
+    pub fn mount_sys(mut self, enabled: bool) -> Self {
+        self.mount_sys = enabled;
+        self
+    }
+
     pub fn mount_dev(mut self, enabled: bool) -> Self {
         self.mount_dev = enabled;
         self
     }
 
+    pub fn network_namespace(mut self, enabled: bool) -> Self {
+        self.network_namespace = enabled;
+        self
+    }
+
+    /// Join an existing network namespace instead of creating one.
+    pub fn network_path(mut self, path: Option<&'a Path>) -> Self {
+        self.network_path = path;
+        self
+    }
+
+    pub fn ipc_namespace(mut self, enabled: bool) -> Self {
+        self.ipc_namespace = enabled;
+        self
+    }
+
+    pub fn cgroup_namespace(mut self, enabled: bool) -> Self {
+        self.cgroup_namespace = enabled;
+        self
+    }
+
+    /// Extra mounts, performed in order inside the rootfs before pivot_root.
+    pub fn mounts(mut self, mounts: &'a [Mount]) -> Self {
+        self.mounts = mounts;
+        self
+    }
+
+    /// Mounted after the pivot, on top of the built-in /proc; one on /dev
+    /// replaces the built-in one
+    pub fn tmpfs(mut self, tmpfs: &'a [Tmpfs]) -> Self {
+        self.tmpfs = tmpfs;
+        self
+    }
+
+    pub fn devices(mut self, devices: &'a [Device]) -> Self {
+        self.devices = devices;
+        self
+    }
+
+    /// Hidden once everything else is mounted, so they cover the fresh
+    /// /proc and /sys
+    pub fn masked_paths(mut self, paths: &'a [PathBuf]) -> Self {
+        self.masked_paths = paths;
+        self
+    }
+
+    pub fn readonly_paths(mut self, paths: &'a [PathBuf]) -> Self {
+        self.readonly_paths = paths;
+        self
+    }
+
+    /// Pivot into an overlay with `rootfs` as its read-only lower layer
+    /// instead of into `rootfs` itself.
+    pub fn overlay(mut self, overlay: Option<&'a Overlay>) -> Self {
+        self.overlay = overlay;
+        self
+    }
+
+    /// Remount "/" read-only at the very end; mounts on top stay writable
+    pub fn readonly_rootfs(mut self, enabled: bool) -> Self {
+        self.readonly_rootfs = enabled;
+        self
+    }
+
+    pub fn cgroup(mut self, cgroup: Option<&'a Cgroup>) -> Self {
+        self.cgroup = cgroup;
+        self
+    }
+
+    /// What ends up on /dev. A `--tmpfs /dev` takes the place of podrun's
+    /// own, so it is mounted before the nodes are created on it.
+    fn dev(&self) -> Dev<'a> {
+        let dev = Path::new("/dev");
+        if let Some(t) = self.tmpfs.iter().find(|t| t.destination == dev) {
+            Dev::Tmpfs(t)
+        } else if self.mount_dev {
+            Dev::Ours
+        } else if self.mounts.iter().any(|m| m.destination == dev) {
+            Dev::Mounted
+        } else {
+            Dev::Image
+        }
+    }
+
+    /// A user namespace, if any, is already in place when this runs: the
+    /// supervisor enters it before the PID namespace, so that one, and the
+    /// mount, UTS and other namespaces unshared below, are all owned by it.
     pub fn apply(self) -> Result<()> {
+        // join while the host's /sys/fs/cgroup is still reachable
+        if let Some(cg) = self.cgroup {
+            cg.add_pid(0)?;
+        }
+
         // isolation and propagation control
         enter_mount_and_uts_namespaces()?;
         make_mounts_private()?;
 
+        if let Some(path) = self.network_path {
+            join_network_namespace(path)?;
+        } else if self.network_namespace {
+            enter_network_namespace()?;
+            bring_up_loopback()?;
+        }
+
+        if self.ipc_namespace {
+            enter_ipc_namespace()?;
+        }
+
+        if self.cgroup_namespace {
+            enter_cgroup_namespace()?;
+        }
+
         // rootfs becomes "/"
-        ensure_rootfs_is_mountpoint(self.rootfs)?;
-        pivot_root_into(self.rootfs)?;
+        let rootfs = match self.overlay {
+            Some(overlay) => {
+                overlay.mount(self.rootfs)?;
+                overlay.merged.as_path()
+            }
+            None => {
+                ensure_rootfs_is_mountpoint(self.rootfs)?;
+                self.rootfs
+            }
+        };
+
+        for m in self.mounts {
+            mount_in_rootfs(rootfs, m)?;
+        }
+
+        pivot_root_into(rootfs)?;
         chdir_to_container_root()?;
 
         // basic virtual filesystems
@@ -54,14 +224,99 @@ impl<'a> ContainerSetup<'a> {
             mount_proc()?;
         }
 
-        if self.mount_dev {
-            mount_minimal_dev()?;
+        if self.mount_sys {
+            mount_sys()?;
+        }
+
+        let dev = self.dev();
+        match dev {
+            Dev::Ours => mount_tmpfs(Path::new("/dev"), &["nosuid".into(), "mode=755".into()])?,
+            Dev::Tmpfs(t) => mount_tmpfs(&t.destination, &t.options)?,
+            Dev::Mounted | Dev::Image => {}
+        }
+        if matches!(dev, Dev::Ours | Dev::Tmpfs(_)) {
+            // mqueue shows the IPC namespace of whoever mounts it, and only
+            // its owner may
+            mount_dev_filesystems(self.ipc_namespace || !in_user_namespace())?;
+        }
+        // before the old root goes, rootless binds the host's nodes from it
+        if !matches!(dev, Dev::Image) {
+            create_devices(self.devices)?;
+            create_default_devices()?;
+        }
+
+        for t in self.tmpfs {
+            // mounted above
+            if t.destination != Path::new("/dev") {
+                mount_tmpfs(&t.destination, &t.options)?;
+            }
+        }
+
+        // read-only first: a masked path may sit below a read-only one
+        for path in self.readonly_paths {
+            make_readonly(path)?;
+        }
+        for path in self.masked_paths {
+            mask_path(path)?;
         }
 
         set_container_hostname(self.hostname)?;
 
         detach_and_remove_old_root()?;
 
+        // last: everything above may create mountpoints, and the old root's
+        // directory is only removed just now
+        if self.readonly_rootfs {
+            remount_readonly(Path::new("/"))?;
+        }
+
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tmpfs(dest: &str) -> Tmpfs {
+        Tmpfs {
+            destination: dest.into(),
+            options: vec![],
+        }
+    }
+
+    #[test]
+    fn dev_is_ours_by_default() {
+        let setup = ContainerSetup::new(Path::new("/rootfs"));
+        assert!(matches!(setup.dev(), Dev::Ours));
+    }
+
+    #[test]
+    fn tmpfs_on_dev_replaces_ours_and_gets_the_nodes() {
+        let tmpfs = [tmpfs("/tmp"), tmpfs("/dev")];
+        let setup = ContainerSetup::new(Path::new("/rootfs"))
+            .mount_dev(false)
+            .tmpfs(&tmpfs);
+        assert!(matches!(setup.dev(), Dev::Tmpfs(t) if t.destination == Path::new("/dev")));
+    }
+
+    #[test]
+    fn spec_mount_on_dev_is_used_as_is() {
+        let mounts = [Mount {
+            destination: "/dev".into(),
+            kind: "tmpfs".into(),
+            source: None,
+            options: vec![],
+        }];
+        let setup = ContainerSetup::new(Path::new("/rootfs"))
+            .mount_dev(false)
+            .mounts(&mounts);
+        assert!(matches!(setup.dev(), Dev::Mounted));
+    }
+
+    #[test]
+    fn no_nodes_in_the_images_own_dev() {
+        let setup = ContainerSetup::new(Path::new("/rootfs")).mount_dev(false);
+        assert!(matches!(setup.dev(), Dev::Image));
+    }
+}
@@ -0,0 +1,163 @@
+use super::isolation::nix_to_io;
+use crate::runtime::spec::IdMapping;
+use crate::runtime::{Result, RuntimeError};
+use nix::unistd::{Gid, Uid, User};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const SUBUID: &str = "/etc/subuid";
+const SUBGID: &str = "/etc/subgid";
+
+/// Mappings for a rootless container: the caller becomes root inside, and
+/// their subordinate ids (if any, and if the setuid helpers that may use
+/// them are installed) cover uids/gids 1 and up.
+pub fn rootless_mappings() -> Result<(Vec<IdMapping>, Vec<IdMapping>)> {
+    let uid = Uid::current();
+    let gid = Gid::current();
+    let name = User::from_uid(uid)
+        .map_err(nix_to_io)?
+        .map(|u| u.name)
+        .unwrap_or_default();
+
+    let mut uids = vec![own_id(uid.as_raw())];
+    let mut gids = vec![own_id(gid.as_raw())];
+
+    if helper("newuidmap").is_some() && helper("newgidmap").is_some() {
+        if let Some((start, count)) = subordinate_range(Path::new(SUBUID), &name, uid.as_raw())? {
+            uids.push(IdMapping {
+                container_id: 1,
+                host_id: start,
+                size: count,
+            });
+        }
+        if let Some((start, count)) = subordinate_range(Path::new(SUBGID), &name, uid.as_raw())? {
+            gids.push(IdMapping {
+                container_id: 1,
+                host_id: start,
+                size: count,
+            });
+        }
+    }
+
+    Ok((uids, gids))
+}
+
+fn own_id(host_id: u32) -> IdMapping {
+    IdMapping {
+        container_id: 0,
+        host_id,
+        size: 1,
+    }
+}
+
+/// First `name:start:count` (or `uid:start:count`) entry for the user.
+fn subordinate_range(file: &Path, name: &str, uid: u32) -> Result<Option<(u32, u32)>> {
+    let content = match fs::read_to_string(file) {
+        Ok(c) => c,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    for line in content.lines() {
+        let mut fields = line.trim().split(':');
+        let (Some(owner), Some(start), Some(count)) = (fields.next(), fields.next(), fields.next())
+        else {
+            continue;
+        };
+        if owner != name && owner.parse::<u32>().ok() != Some(uid) {
+            continue;
+        }
+        if let (Ok(start), Ok(count)) = (start.parse(), count.parse()) {
+            return Ok(Some((start, count)));
+        }
+    }
+    Ok(None)
+}
+
+/// Writes uid_map and gid_map of `pid`, which must already be in its new
+/// user namespace. Root writes them directly; otherwise the kernel only lets
+/// us map our own ids, so anything more goes through newuidmap/newgidmap.
+pub fn write_mappings(pid: i32, uids: &[IdMapping], gids: &[IdMapping]) -> Result<()> {
+    let proc = PathBuf::from(format!("/proc/{pid}"));
+
+    if Uid::effective().is_root() {
+        fs::write(proc.join("uid_map"), format_mappings(uids))?;
+        fs::write(proc.join("gid_map"), format_mappings(gids))?;
+        return Ok(());
+    }
+
+    if is_own(uids, Uid::effective().as_raw()) {
+        fs::write(proc.join("uid_map"), format_mappings(uids))?;
+    } else {
+        run_helper("newuidmap", pid, uids)?;
+    }
+
+    if is_own(gids, Gid::effective().as_raw()) {
+        // required before an unprivileged gid_map write
+        fs::write(proc.join("setgroups"), "deny")?;
+        fs::write(proc.join("gid_map"), format_mappings(gids))?;
+    } else {
+        run_helper("newgidmap", pid, gids)?;
+    }
+
+    Ok(())
+}
+
+/// Whether setgroups(2) was disabled for this user namespace, as it is when
+/// the gid map was written without newgidmap.
+pub fn setgroups_denied() -> bool {
+    fs::read_to_string("/proc/self/setgroups").is_ok_and(|s| s.trim() == "deny")
+}
+
+/// Whether this process runs in a user namespace other than the initial one.
+pub fn in_user_namespace() -> bool {
+    fs::read_to_string("/proc/self/uid_map").is_ok_and(|m| {
+        let fields: Vec<&str> = m.split_whitespace().collect();
+        fields != ["0", "0", "4294967295"]
+    })
+}
+
+fn is_own(maps: &[IdMapping], id: u32) -> bool {
+    matches!(maps, [m] if m.host_id == id && m.size == 1)
+}
+
+fn format_mappings(maps: &[IdMapping]) -> String {
+    maps.iter()
+        .map(|m| format!("{} {} {}\n", m.container_id, m.host_id, m.size))
+        .collect()
+}
+
+fn run_helper(name: &str, pid: i32, maps: &[IdMapping]) -> Result<()> {
+    let path = helper(name).ok_or_else(|| {
+        RuntimeError::Msg(format!(
+            "mapping more than your own id needs {name} (usually in the uidmap package)"
+        ))
+    })?;
+
+    let mut cmd = Command::new(path);
+    cmd.arg(pid.to_string());
+    for m in maps {
+        cmd.args([
+            m.container_id.to_string(),
+            m.host_id.to_string(),
+            m.size.to_string(),
+        ]);
+    }
+
+    let out = cmd.output()?;
+    if !out.status.success() {
+        return Err(RuntimeError::Msg(format!(
+            "{name} failed: {}",
+            String::from_utf8_lossy(&out.stderr).trim()
+        )));
+    }
+    Ok(())
+}
+
+fn helper(name: &str) -> Option<PathBuf> {
+    let path = std::env::var_os("PATH")?;
+    std::env::split_paths(&path)
+        .map(|dir| dir.join(name))
+        .find(|p| p.is_file())
+}
@@ -0,0 +1,27 @@
+use crate::cli::LogFormat;
+use crate::runtime::unix::{now_unix, rfc3339};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+
+/// Appends `err` to the `--log` file in the format container engines parse
+/// out of runc's logs (logrus text or JSON lines).
+pub fn log_error(path: &Path, format: LogFormat, err: &dyn std::fmt::Display) {
+    let time = rfc3339(now_unix());
+    let msg = err.to_string();
+
+    let line = match format {
+        LogFormat::Text => format!("time={time:?} level=error msg={msg:?}"),
+        LogFormat::Json => serde_json::json!({
+            "level": "error",
+            "msg": msg,
+            "time": time,
+        })
+        .to_string(),
+    };
+
+    // logging must never mask the error being logged
+    if let Ok(mut f) = OpenOptions::new().create(true).append(true).open(path) {
+        let _ = writeln!(f, "{line}");
+    }
+}
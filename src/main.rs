@@ -1,21 +1,160 @@
 use clap::Parser;
+use commands::create::SpecArgs;
+use commands::exec::ProcessArgs;
+use runtime::container::CreateOptions;
+use runtime::spec::{NetworkMode, Resources};
+use runtime::store::Store;
+use std::path::PathBuf;
 
 mod cli;
 mod commands;
 mod linux;
+mod logging;
 mod runtime;
 
 fn main() -> runtime::Result<()> {
     let cli = cli::Cli::parse();
-    match cli.cmd {
-        cli::Cmd::Create { id, rootfs, argv } => commands::create::cmd_create(id, rootfs, argv)?,
+
+    let res = match cli.root {
+        Some(root) => Store::set_root(root),
+        None => Ok(()),
+    }
+    .and_then(|()| run(cli.cmd));
+
+    if let (Err(e), Some(path)) = (&res, &cli.log) {
+        logging::log_error(path, cli.log_format, e);
+    }
+    res
+}
+
+fn run(cmd: cli::Cmd) -> runtime::Result<()> {
+    match cmd {
+        cli::Cmd::Create(args) => create(args, None, commands::create::cmd_create)?,
+        cli::Cmd::Run {
+            create: args,
+            seccomp_learn,
+        } => create(args, seccomp_learn, commands::run::cmd_run)?,
         cli::Cmd::Start { id } => commands::start::cmd_start(id)?,
         cli::Cmd::Kill { id, signal } => commands::kill::cmd_kill(id, signal)?,
-        cli::Cmd::Delete { id } => commands::delete::cmd_delete(id)?,
+        cli::Cmd::Delete { id, force } => commands::delete::cmd_delete(id, force)?,
+        cli::Cmd::Pause { id } => commands::pause::cmd_pause(id)?,
+        cli::Cmd::Resume { id } => commands::resume::cmd_resume(id)?,
         cli::Cmd::Wait { id } => commands::wait::cmd_wait(id)?,
-        cli::Cmd::Exec { id, argv, env, cwd } => commands::exec::cmd_exec(id, argv, env, cwd)?,
+        cli::Cmd::Exec {
+            id,
+            argv,
+            env,
+            cwd,
+            cap_add,
+            cap_drop,
+            ulimits,
+            no_new_privileges,
+            oom_score_adj,
+        } => commands::exec::cmd_exec(
+            id,
+            argv,
+            ProcessArgs {
+                env,
+                cwd,
+                cap_add,
+                cap_drop,
+                ulimits,
+                no_new_privileges,
+                oom_score_adj,
+            },
+        )?,
         cli::Cmd::State { id, json } => commands::state::cmd_state(id, json)?,
         cli::Cmd::List => commands::list::cmd_list()?,
+        cli::Cmd::Volume { cmd } => match cmd {
+            cli::VolumeCmd::Create { name } => commands::volume::cmd_create(name)?,
+            cli::VolumeCmd::Ls => commands::volume::cmd_ls()?,
+            cli::VolumeCmd::Inspect { names } => commands::volume::cmd_inspect(names)?,
+            cli::VolumeCmd::Rm { names } => commands::volume::cmd_rm(names)?,
+            cli::VolumeCmd::Prune => commands::volume::cmd_prune()?,
+        },
+        cli::Cmd::Stats {
+            ids,
+            no_stream,
+            json,
+        } => commands::stats::cmd_stats(ids, no_stream, json)?,
     }
     Ok(())
 }
+
+type CreateFn = fn(
+    String,
+    Option<PathBuf>,
+    Vec<String>,
+    NetworkMode,
+    Resources,
+    SpecArgs,
+    CreateOptions,
+) -> runtime::Result<()>;
+
+/// Hands the options `create` and `run` share to either of them.
+fn create(
+    args: cli::CreateArgs,
+    seccomp_learn: Option<PathBuf>,
+    cmd: CreateFn,
+) -> runtime::Result<()> {
+    let cli::CreateArgs {
+        id,
+        rootfs,
+        bundle,
+        pid_file,
+        console_socket,
+        overlay,
+        read_only,
+        read_only_tmpfs,
+        mounts,
+        volumes,
+        tmpfs,
+        devices,
+        cap_add,
+        cap_drop,
+        seccomp_profile,
+        no_new_privileges,
+        ulimits,
+        oom_score_adj,
+        hooks,
+        network,
+        memory,
+        cpus,
+        pids_limit,
+        argv,
+    } = args;
+
+    cmd(
+        id,
+        rootfs,
+        argv,
+        network,
+        Resources {
+            memory,
+            cpus,
+            pids_limit,
+            ..Default::default()
+        },
+        SpecArgs {
+            overlay,
+            read_only,
+            read_only_tmpfs,
+            mounts: mounts.into_iter().chain(volumes).collect(),
+            tmpfs,
+            devices,
+            cap_add,
+            cap_drop,
+            seccomp_profile,
+            no_new_privileges,
+            ulimits,
+            oom_score_adj,
+            hooks,
+        },
+        CreateOptions {
+            bundle,
+            pid_file,
+            console_socket,
+            seccomp_learn,
+        },
+    )
+}
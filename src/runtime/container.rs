@@ -1,17 +1,27 @@
-use super::unix::now_unix;
-use crate::linux::isolation::nix_to_io;
+use crate::linux::capabilities;
+use crate::linux::cgroup::Cgroup;
+use crate::linux::console;
+use crate::linux::devices;
+use crate::linux::exec_fifo;
+use crate::linux::isolation::{enter_pid_namespace, nix_to_io};
+use crate::linux::mounts::Overlay;
+use crate::linux::process::ProcessSetup;
+use crate::linux::seccomp;
 use crate::linux::setup::ContainerSetup;
+use crate::linux::userns;
+use crate::runtime::spec::{Capabilities, DEFAULT_PATH, HookStage, NetworkMode, Rlimit, Spec};
 use crate::runtime::state::{State, Status};
-use crate::runtime::{Result, RuntimeError, spec::Spec, store::Store};
+use crate::runtime::unix::{now_unix, rfc3339};
+use crate::runtime::{Result, RuntimeError, hooks, network, oci, store::Store, supervisor, volume};
 use nix::sched::CloneFlags;
-use nix::sys::signal::{self};
-use nix::unistd::Pid;
+use nix::sys::signal::{self, Signal};
+use nix::unistd::{Pid, Uid};
 use std::fs;
 use std::fs::File;
 use std::io;
 use std::os::fd::AsFd;
 use std::os::unix::process::CommandExt;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::process::Stdio;
 use std::{thread, time::Duration};
@@ -23,50 +33,161 @@ pub struct Container {
     state: State,
 }
 
+/// Per-invocation options of `create` that aren't part of the spec itself.
+#[derive(Debug, Default)]
+pub struct CreateOptions {
+    pub bundle: Option<PathBuf>,
+    pub pid_file: Option<PathBuf>,
+    pub console_socket: Option<PathBuf>,
+    /// Record init's syscalls instead of filtering them, and write them here
+    /// as an allow-list profile once it exits
+    pub seccomp_learn: Option<PathBuf>,
+}
+
+/// Per-invocation options of `exec`, on top of what the spec gives every
+/// process.
+#[derive(Debug, Default)]
+pub struct ExecOptions {
+    pub env: Vec<(String, String)>,
+    pub cwd: Option<PathBuf>,
+    /// Replaces the spec's sets for this process
+    pub capabilities: Option<Capabilities>,
+    /// Replace the spec's limits of the same type
+    pub rlimits: Vec<Rlimit>,
+    /// Sets no_new_privs even if the spec doesn't
+    pub no_new_privileges: bool,
+    pub oom_score_adj: Option<i32>,
+}
+
 impl Container {
-    pub fn create(id: String, rootfs: PathBuf, argv: Vec<String>) -> Result<Self> {
+    pub fn create(id: String, mut spec: Spec, opts: CreateOptions) -> Result<Self> {
         let store = Store::new()?;
 
         if store.exists(&id) {
             return Err(RuntimeError::Msg(format!("container {id} already exists")));
         }
 
-        if argv.is_empty() {
+        if spec.argv.is_empty() {
             return Err(RuntimeError::Msg("no command provided".into()));
         }
 
-        if !rootfs.exists() {
+        if !spec.rootfs.exists() {
             return Err(RuntimeError::Msg(format!(
                 "rootfs does not exist: {}",
-                rootfs.display()
+                spec.rootfs.display()
             )));
         }
 
-        if !rootfs.is_dir() {
+        if !spec.rootfs.is_dir() {
             return Err(RuntimeError::Msg(format!(
                 "rootfs is not a directory: {}",
-                rootfs.display()
+                spec.rootfs.display()
             )));
         }
 
-        let spec = Spec {
-            rootfs,
-            argv,
-            env: vec![],
-            cwd: None,
-            hostname: Some(id.clone()),
-        };
+        // start may run from a different working directory
+        spec.rootfs = spec.rootfs.canonicalize()?;
 
-        let state = State::new_created(id.clone());
+        if spec.hostname.is_none() {
+            spec.hostname = Some(id.clone());
+        }
+
+        // rootless: the caller is root only inside a user namespace of its own
+        if !Uid::effective().is_root() && !spec.namespaces.user {
+            let (uids, gids) = userns::rootless_mappings()?;
+            spec.namespaces.user = true;
+            spec.uid_mappings = uids;
+            spec.gid_mappings = gids;
+        }
+
+        // The device policy is an eBPF program on the cgroup v2. Without one,
+        // nothing would stop container root from creating and opening a host
+        // disk's node, so it doesn't get to create nodes.
+        if Uid::effective().is_root() && !Cgroup::supported() {
+            let caps = spec
+                .capabilities
+                .get_or_insert_with(capabilities::default_set);
+            if caps.bounding.iter().any(|c| c == "CAP_MKNOD") {
+                capabilities::adjust(caps, &[], &["CAP_MKNOD".into()])?;
+                eprintln!("warning: no cgroup v2 to enforce the device policy, dropping CAP_MKNOD");
+            }
+        }
+
+        if spec.namespaces.user {
+            if spec.uid_mappings.is_empty() || spec.gid_mappings.is_empty() {
+                return Err(RuntimeError::Msg(
+                    "a user namespace needs uid and gid mappings".into(),
+                ));
+            }
+            // the veth is moved in from the host side, which needs real root
+            if spec.network == NetworkMode::Bridge {
+                return Err(RuntimeError::Msg(
+                    "bridge networking is not available with a user namespace (rootless); use --network none or host".into(),
+                ));
+            }
+        }
+
+        // named volumes become bind mounts of their directory in the store
+        for m in spec.mounts.iter_mut().filter(|m| m.kind == "volume") {
+            let name = m
+                .source
+                .as_deref()
+                .and_then(Path::to_str)
+                .ok_or_else(|| {
+                    RuntimeError::Msg(format!(
+                        "volume mount on {} has no volume name",
+                        m.destination.display()
+                    ))
+                })?
+                .to_string();
+            m.source = Some(volume::mountpoint(&store, &name)?);
+            m.kind = "bind".into();
+            m.options.insert(0, "rbind".into());
+            if !spec.volumes.contains(&name) {
+                spec.volumes.push(name);
+            }
+        }
+
+        match (spec.terminal, &opts.console_socket) {
+            (true, None) => {
+                return Err(RuntimeError::Msg(
+                    "process.terminal needs --console-socket".into(),
+                ));
+            }
+            (false, Some(_)) => {
+                return Err(RuntimeError::Msg(
+                    "--console-socket given but process.terminal is false".into(),
+                ));
+            }
+            _ => {}
+        }
+
+        let mut state = State::new_created(id.clone());
+        state.bundle = opts.bundle.as_ref().map(|b| b.canonicalize()).transpose()?;
 
         store.create_container(&id, &spec, &state)?;
 
-        Ok(Self {
+        let mut c = Self {
             id,
             store,
             spec,
             state,
-        })
+        };
+
+        // a half-created container is of no use to anyone, unlike a stopped one
+        let created = c
+            .acquire_volumes()
+            .and_then(|()| c.spawn_init(&opts))
+            .and_then(|()| hooks::run(HookStage::CreateRuntime, &c.spec.hooks, &c.oci_state()?));
+        if let Err(e) = created {
+            if c.state.pid.is_some() {
+                let _ = c.kill(Signal::SIGKILL as i32);
+            }
+            let _ = c.release_resources();
+            return Err(e);
+        }
+
+        Ok(c)
     }
 
     pub fn open(id: String) -> Result<Self> {
@@ -87,67 +208,229 @@ impl Container {
         })
     }
 
-    pub fn start(&mut self) -> Result<i32> {
-        if matches!(self.state.status, Status::Running) {
-            return Err(RuntimeError::Msg("already running".into()));
-        }
-        if self.spec.argv.is_empty() {
-            return Err(RuntimeError::Msg("spec argv is empty".into()));
-        }
-
+    /// Sets up namespaces, cgroup and mounts, and leaves init blocked on the
+    /// exec FIFO until `start`.
+    fn spawn_init(&mut self, opts: &CreateOptions) -> Result<()> {
         // Container program path
         let prog = self.spec.argv[0].clone();
-
-        // Validate
-        let rel = prog.trim_start_matches('/');
-        let in_rootfs = self.spec.rootfs.join(rel);
-        if !in_rootfs.exists() {
-            return Err(RuntimeError::Msg(format!(
-                "executable not found in rootfs: {} (from {})",
-                in_rootfs.display(),
-                prog
-            )));
-        }
+        let exe = self.resolve_executable(&prog)?;
 
         let args: Vec<String> = self.spec.argv.iter().skip(1).cloned().collect();
 
-        let rootfs = self.spec.rootfs.clone();
-        let hostname = self.spec.hostname.clone();
-
-        let mut cmd = Command::new(&prog);
-        cmd.args(&args);
+        let spec = self.spec.clone();
+        // bridged namespaces are created by the supervisor, see below
+        let own_network =
+            spec.network == NetworkMode::None && spec.namespaces.network_path.is_none();
+
+        // Without cgroup v2 we can still run, just not enforce any limits (see
+        // create for devices). Rootless, only ask for one when limits need it
+        // (and it's delegated).
+        let want_cgroup = Cgroup::supported() && Uid::effective().is_root();
+        let cgroup = if want_cgroup || !spec.resources.is_empty() {
+            let cg = Cgroup::for_container(&self.id);
+            cg.create(&spec.resources).map_err(|e| {
+                if Uid::effective().is_root() {
+                    return e;
+                }
+                RuntimeError::Msg(format!(
+                    "resource limits without root need a delegated cgroup v2 subtree: {e}"
+                ))
+            })?;
+            // loading the eBPF program takes privileges rootless doesn't have
+            if Uid::effective().is_root() {
+                cg.restrict_devices(&devices::policy(&spec))?;
+            }
+            Some(cg)
+        } else {
+            None
+        };
 
-        if let Some(cwd) = &self.spec.cwd {
-            cmd.current_dir(cwd);
+        let overlay = spec
+            .overlay
+            .then(|| Overlay::in_dir(&self.store.dir(&self.id)));
+        if let Some(o) = &overlay {
+            o.create_dirs()?;
         }
 
-        for (k, v) in &self.spec.env {
+        let filter = match (&opts.seccomp_learn, &spec.seccomp) {
+            (Some(_), _) => Some(seccomp::learning_filter()?),
+            (None, Some(profile)) => Some(seccomp::compile(profile, spec.capabilities.as_ref())?),
+            (None, None) => None,
+        };
+
+        let mut cmd = Command::new(&exe);
+        cmd.arg0(&prog).args(&args);
+
+        // a bundle's process.env is the whole environment; --rootfs
+        // containers inherit the caller's and only add to it
+        if self.state.bundle.is_some() {
+            cmd.env_clear();
+        }
+        for (k, v) in &spec.env {
             cmd.env(k, v);
         }
 
+        let terminal = match &opts.console_socket {
+            Some(socket) if spec.terminal => {
+                let pty = console::send_pty_master(socket)?;
+                cmd.stdin(pty.try_clone()?)
+                    .stdout(pty.try_clone()?)
+                    .stderr(pty);
+                true
+            }
+            _ => false,
+        };
+
         unsafe {
             cmd.pre_exec(move || {
-                ContainerSetup::new(&rootfs)
-                    .hostname(hostname.as_deref())
-                    .mount_proc(true)
-                    .mount_dev(true)
+                if terminal {
+                    console::set_controlling_tty()?;
+                }
+
+                ContainerSetup::new(&spec.rootfs)
+                    .hostname(spec.hostname.as_deref())
+                    .mount_proc(!spec.has_mount_at("/proc"))
+                    .mount_sys(!spec.has_mount_at("/sys"))
+                    .mount_dev(!spec.has_mount_at("/dev"))
+                    .network_namespace(own_network)
+                    .network_path(spec.namespaces.network_path.as_deref())
+                    .ipc_namespace(spec.namespaces.ipc)
+                    .cgroup_namespace(spec.namespaces.cgroup)
+                    .cgroup(cgroup.as_ref())
+                    .mounts(&spec.mounts)
+                    .tmpfs(&spec.tmpfs)
+                    .devices(&spec.devices)
+                    .masked_paths(&spec.masked_paths)
+                    .readonly_paths(&spec.readonly_paths)
+                    .overlay(overlay.as_ref())
+                    .readonly_rootfs(spec.readonly_rootfs)
                     .apply()
-                    .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+                    .map_err(|e| io::Error::other(e.to_string()))?;
+
+                ProcessSetup::new()
+                    .rlimits(&spec.rlimits)
+                    .oom_score_adj(spec.oom_score_adj)
+                    .no_new_privileges(spec.no_new_privileges)
+                    .seccomp(filter.as_ref())
+                    .user(spec.user.as_ref())
+                    .capabilities(spec.capabilities.as_ref())
+                    .cwd(spec.cwd.as_deref())
+                    .apply()
+                    .map_err(|e| io::Error::other(e.to_string()))?;
                 Ok(())
             });
         }
 
-        let child = cmd.spawn()?;
-        let pid = child.id() as i32;
+        let attachment = match self.spec.network {
+            NetworkMode::Bridge => Some(network::allocate(&self.store, &self.id)?),
+            _ => None,
+        };
+        self.state = self.store.update_state(&self.id, |state| {
+            state.network = attachment.clone();
+            Ok(())
+        })?;
+
+        let exec_fifo = exec_fifo::create(&self.exec_fifo_path())?;
+
+        // the supervisor records init's pid (and later the exit status) itself
+        let id_mappings = self
+            .spec
+            .namespaces
+            .user
+            .then_some((&self.spec.uid_mappings[..], &self.spec.gid_mappings[..]));
+        let pid_namespace = self.spec.namespaces.pid;
+        let seccomp_learn = opts.seccomp_learn.as_deref();
+        let pid = supervisor::spawn(
+            &self.id,
+            cmd,
+            exec_fifo,
+            id_mappings,
+            seccomp_learn,
+            move || {
+                if pid_namespace {
+                    enter_pid_namespace()?;
+                }
+                if let Some(att) = &attachment {
+                    network::enter_bridged_namespace(att)?;
+                }
+                Ok(())
+            },
+        )?;
+        self.state = self.store.load_state(&self.id)?;
+
+        if let Some(path) = &opts.pid_file {
+            write_pid_file(path, pid)?;
+        }
+
+        Ok(())
+    }
+
+    /// Lets the init parked by `create` exec the container process.
+    pub fn start(&mut self) -> Result<i32> {
+        self.sync_stopped()?;
+        if self.state.status != Status::Created {
+            return Err(RuntimeError::Msg(format!(
+                "cannot start: container is {:?}",
+                self.state.status
+            )));
+        }
+
+        let pid = self
+            .state
+            .pid
+            .ok_or_else(|| RuntimeError::Msg("container has no init process".into()))?;
+
+        if let Err(e) = hooks::run(HookStage::Prestart, &self.spec.hooks, &self.oci_state()?) {
+            // init never gets to exec; the container ends up stopped
+            self.kill(Signal::SIGKILL as i32)?;
+            return Err(e);
+        }
 
-        self.state.status = Status::Running;
-        self.state.pid = Some(pid);
-        self.state.started_at_unix = Some(now_unix());
+        // before the release, so it can't land after the supervisor's Stopped
+        self.state = self.store.update_state(&self.id, |state| {
+            if state.status != Status::Created {
+                return Err(RuntimeError::Msg(format!(
+                    "cannot start: container is {:?}",
+                    state.status
+                )));
+            }
+            state.status = Status::Running;
+            state.started_at_unix = Some(now_unix());
+            Ok(())
+        })?;
+
+        if let Err(e) = exec_fifo::release(&self.exec_fifo_path(), pid) {
+            // init never got to exec, or is gone; don't leave it Running
+            self.kill(Signal::SIGKILL as i32)?;
+            return Err(e);
+        }
 
-        self.store.save_state(&self.id, &self.state)?;
+        hooks::run_and_warn(HookStage::Poststart, &self.spec.hooks, &self.oci_state()?);
         Ok(pid)
     }
 
+    /// The container's state as the OCI runtime-spec describes it.
+    pub fn oci_state(&self) -> Result<oci::State> {
+        Ok(oci::State {
+            oci_version: oci::OCI_VERSION,
+            id: self.id.clone(),
+            status: self.state.status.oci_name(),
+            pid: self.state.pid.filter(|_| self.state.status.is_live()),
+            // containers made with --rootfs have no bundle; their spec lives here
+            bundle: match &self.state.bundle {
+                Some(b) => b.clone(),
+                None => self.store.dir(&self.id),
+            },
+            rootfs: self.spec.rootfs.clone(),
+            created: rfc3339(self.state.created_at_unix),
+            annotations: self.spec.annotations.clone(),
+        })
+    }
+
+    fn exec_fifo_path(&self) -> PathBuf {
+        self.store.dir(&self.id).join("exec.fifo")
+    }
+
     pub fn kill(&mut self, signal_num: i32) -> Result<()> {
         let pid_i32 = self
             .state
@@ -160,24 +443,26 @@ impl Container {
 
         // If it's already dead, treat as success and update state
         if !Self::proc_exists(pid_i32) {
-            self.state.status = Status::Stopped;
-            self.state.pid = None;
-            self.store.save_state(&self.id, &self.state)?;
-            return Ok(());
+            return self.sync_stopped();
         }
 
+        // A frozen init would only see SIGKILL once thawed; other signals are
+        // queued and delivered on resume, leaving the container paused.
+        let thaw = self.state.status == Status::Paused && sig == Some(Signal::SIGKILL);
+
         let res = match sig {
             None => signal::kill(pid, None),
             Some(s) => signal::kill(pid, Some(s)),
         };
 
+        if thaw {
+            Cgroup::for_container(&self.id).set_frozen(false)?;
+        }
+
         if let Err(e) = res {
             // ESRCH - "no such process"
             if e == nix::errno::Errno::ESRCH {
-                self.state.status = Status::Stopped;
-                self.state.pid = None;
-                self.store.save_state(&self.id, &self.state)?;
-                return Ok(());
+                return self.sync_stopped();
             }
             return Err(RuntimeError::Io(nix_to_io(e)));
         }
@@ -185,34 +470,120 @@ impl Container {
         if sig.is_some() {
             for _ in 0..50 {
                 if !Self::proc_exists(pid_i32) {
-                    self.state.status = Status::Stopped;
-                    self.state.pid = None;
-                    self.store.save_state(&self.id, &self.state)?;
-                    return Ok(());
+                    return self.sync_stopped();
                 }
                 thread::sleep(Duration::from_millis(10));
             }
         }
 
-        self.state.status = Status::Running;
-        self.store.save_state(&self.id, &self.state)?;
+        Ok(())
+    }
 
+    pub fn pause(&mut self) -> Result<()> {
+        self.sync_stopped()?;
+        if self.state.status != Status::Running {
+            return Err(RuntimeError::Msg(format!(
+                "cannot pause: container is {:?}",
+                self.state.status
+            )));
+        }
+
+        let freezer = self.freezer()?;
+        self.state = self.store.update_state(&self.id, |state| {
+            if state.status != Status::Running {
+                return Err(RuntimeError::Msg(format!(
+                    "cannot pause: container is {:?}",
+                    state.status
+                )));
+            }
+            freezer.set_frozen(true)?;
+            state.status = Status::Paused;
+            Ok(())
+        })?;
         Ok(())
     }
 
-    pub fn delete(self) -> Result<()> {
-        if matches!(self.state.status, Status::Running) {
-            if let Some(pid) = self.state.pid {
-                if Self::proc_exists(pid) {
-                    return Err(RuntimeError::Msg(
-                        "refusing to delete: container is Running (kill it first)".into(),
-                    ));
-                }
+    pub fn resume(&mut self) -> Result<()> {
+        self.sync_stopped()?;
+        if self.state.status != Status::Paused {
+            return Err(RuntimeError::Msg(format!(
+                "cannot resume: container is {:?}",
+                self.state.status
+            )));
+        }
+
+        let freezer = self.freezer()?;
+        self.state = self.store.update_state(&self.id, |state| {
+            if state.status != Status::Paused {
+                return Err(RuntimeError::Msg(format!(
+                    "cannot resume: container is {:?}",
+                    state.status
+                )));
             }
+            freezer.set_frozen(false)?;
+            state.status = Status::Running;
+            Ok(())
+        })?;
+        Ok(())
+    }
+
+    fn freezer(&self) -> Result<Cgroup> {
+        let cg = Cgroup::for_container(&self.id);
+        if !cg.exists() {
+            return Err(RuntimeError::Msg(
+                "container has no cgroup (pause/resume need cgroup v2)".into(),
+            ));
         }
+        Ok(cg)
+    }
+
+    /// Removes the container; a created one is killed first, like with
+    /// `force` any running one is.
+    pub fn delete(mut self, force: bool) -> Result<()> {
+        self.sync_stopped()?;
+
+        let created = self.state.status == Status::Created && self.state.pid.is_some();
+        if (force || created) && self.state.status.is_live() {
+            self.kill(Signal::SIGKILL as i32)?;
+            self.sync_stopped()?;
+        }
+
+        if self.state.status.is_live() {
+            return Err(RuntimeError::Msg(format!(
+                "refusing to delete: container is {:?} (kill it first)",
+                self.state.status
+            )));
+        }
+
+        let state = self.oci_state()?;
+        self.release_resources()?;
+
+        hooks::run_and_warn(HookStage::Poststop, &self.spec.hooks, &state);
+        Ok(())
+    }
+
+    fn acquire_volumes(&self) -> Result<()> {
+        for name in &self.spec.volumes {
+            volume::acquire(&self.store, name, &self.id)?;
+        }
+        Ok(())
+    }
+
+    fn release_resources(&self) -> Result<()> {
+        if let Some(att) = &self.state.network {
+            network::detach(att)?;
+        }
+        network::release(&self.store, &self.id)?;
+        for name in &self.spec.volumes {
+            volume::release(&self.store, name, &self.id)?;
+        }
+        Cgroup::for_container(&self.id).remove()?;
 
         let dir = self.store.dir(&self.id);
         if dir.exists() {
+            if self.spec.overlay {
+                Overlay::prepare_removal(&dir);
+            }
             fs::remove_dir_all(&dir)?;
         }
 
@@ -220,35 +591,24 @@ impl Container {
     }
 
     pub fn wait(&mut self) -> Result<i32> {
-        let pid = self
-            .state
-            .pid
-            .ok_or_else(|| RuntimeError::Msg("no pid recorded for container".into()))?;
-
-        if !Self::proc_exists(pid) {
-            self.state.status = Status::Stopped;
-            self.state.pid = None;
-            self.store.save_state(&self.id, &self.state)?;
-            return Ok(0);
+        if self.state.pid.is_none() && self.state.exit_code.is_none() {
+            return Err(RuntimeError::Msg("no pid recorded for container".into()));
         }
 
-        while Self::proc_exists(pid) {
+        loop {
+            self.sync_stopped()?;
+            if !self.state.status.is_live() {
+                break;
+            }
             thread::sleep(Duration::from_millis(50));
         }
 
-        self.state.status = Status::Stopped;
-        self.state.pid = None;
-        self.store.save_state(&self.id, &self.state)?;
-
-        Ok(0)
+        self.state
+            .exit_code
+            .ok_or_else(|| RuntimeError::Msg("exit status was not recorded".into()))
     }
 
-    pub fn exec(
-        &self,
-        argv: Vec<String>,
-        env: Vec<(String, String)>,
-        cwd: Option<std::path::PathBuf>,
-    ) -> Result<i32> {
+    pub fn exec(&self, argv: Vec<String>, opts: ExecOptions) -> Result<i32> {
         if argv.is_empty() {
             return Err(RuntimeError::Msg("exec argv is empty".into()));
         }
@@ -262,10 +622,53 @@ impl Container {
             return Err(RuntimeError::Msg("container pid is not alive".into()));
         }
 
-        let ns_mnt = PathBuf::from(format!("/proc/{}/ns/mnt", target_pid));
-        let ns_uts = PathBuf::from(format!("/proc/{}/ns/uts", target_pid));
-        let proc_root = PathBuf::from(format!("/proc/{}/root", target_pid));
-        let cwd = cwd.unwrap_or_else(|| PathBuf::from("/"));
+        if self.state.status == Status::Paused {
+            return Err(RuntimeError::Msg(
+                "container is paused (resume it first)".into(),
+            ));
+        }
+
+        // Opened up front: once the mount namespace is joined, /proc is the
+        // container's own procfs, where init is PID 1 rather than target_pid.
+        let ns_mnt = File::open(format!("/proc/{}/ns/mnt", target_pid))?;
+        let ns_uts = File::open(format!("/proc/{}/ns/uts", target_pid))?;
+        let ns_pid = File::open(format!("/proc/{}/ns/pid", target_pid))?;
+        let ns_user = self
+            .spec
+            .namespaces
+            .user
+            .then(|| File::open(format!("/proc/{}/ns/user", target_pid)))
+            .transpose()?;
+        let ns_net = File::open(format!("/proc/{}/ns/net", target_pid))?;
+        let ns_ipc = self
+            .spec
+            .namespaces
+            .ipc
+            .then(|| File::open(format!("/proc/{}/ns/ipc", target_pid)))
+            .transpose()?;
+        let ns_cgroup = self
+            .spec
+            .namespaces
+            .cgroup
+            .then(|| File::open(format!("/proc/{}/ns/cgroup", target_pid)))
+            .transpose()?;
+        let proc_root = File::open(format!("/proc/{}/root", target_pid))?;
+        let cwd = opts
+            .cwd
+            .or_else(|| self.spec.cwd.clone())
+            .unwrap_or_else(|| PathBuf::from("/"));
+        let mut spec = self.spec.clone();
+        spec.override_rlimits(opts.rlimits);
+        spec.no_new_privileges |= opts.no_new_privileges;
+        spec.oom_score_adj = opts.oom_score_adj.or(spec.oom_score_adj);
+        let capabilities = opts.capabilities.or_else(|| spec.capabilities.clone());
+        let filter = spec
+            .seccomp
+            .as_ref()
+            .map(|p| seccomp::compile(p, capabilities.as_ref()))
+            .transpose()?;
+        let cgroup = Cgroup::for_container(&self.id);
+        let cgroup = cgroup.exists().then_some(cgroup);
 
         let prog = argv[0].clone();
         let args: Vec<String> = argv.into_iter().skip(1).collect();
@@ -273,7 +676,7 @@ impl Container {
         let mut cmd = Command::new(&prog);
         cmd.args(&args);
 
-        for (k, v) in env {
+        for (k, v) in opts.env {
             cmd.env(k, v);
         }
 
@@ -283,37 +686,126 @@ impl Container {
 
         unsafe {
             cmd.pre_exec(move || {
+                if let Some(cg) = &cgroup {
+                    cg.add_pid(0).map_err(|e| io::Error::other(e.to_string()))?;
+                }
+
                 Self::setns_file(&ns_mnt, CloneFlags::CLONE_NEWNS)?;
                 Self::setns_file(&ns_uts, CloneFlags::CLONE_NEWUTS)?;
+                Self::setns_file(&ns_net, CloneFlags::CLONE_NEWNET)?;
+                if let Some(ns) = &ns_ipc {
+                    Self::setns_file(ns, CloneFlags::CLONE_NEWIPC)?;
+                }
+                if let Some(ns) = &ns_cgroup {
+                    Self::setns_file(ns, CloneFlags::CLONE_NEWCGROUP)?;
+                }
 
-                nix::unistd::chdir(&proc_root).map_err(nix_to_io)?;
+                nix::unistd::fchdir(&proc_root).map_err(nix_to_io)?;
                 nix::unistd::chroot(".").map_err(nix_to_io)?;
 
-                nix::unistd::chdir(&cwd).map_err(nix_to_io)?;
+                ProcessSetup::new()
+                    .rlimits(&spec.rlimits)
+                    .oom_score_adj(spec.oom_score_adj)
+                    .no_new_privileges(spec.no_new_privileges)
+                    .seccomp(filter.as_ref())
+                    .user(spec.user.as_ref())
+                    .capabilities(capabilities.as_ref())
+                    .cwd(Some(&cwd))
+                    .apply()
+                    .map_err(|e| io::Error::other(e.to_string()))?;
                 Ok(())
             });
         }
 
+        // The user namespace owns all the others, so it goes first. Like a
+        // PID namespace (which can't be joined from pre_exec as it only
+        // applies to children), join it here and let the command inherit it.
+        if let Some(ns) = &ns_user {
+            Self::setns_file(ns, CloneFlags::CLONE_NEWUSER)?;
+        }
+        Self::setns_file(&ns_pid, CloneFlags::CLONE_NEWPID)?;
+
         let st = cmd.status()?;
         Ok(st.code().unwrap_or(0))
     }
 
     pub fn refresh_state(&mut self) -> Result<()> {
-        if matches!(self.state.status, Status::Running) {
-            match self.state.pid {
-                Some(pid) if Self::proc_exists(pid) => { /* still alive */ }
-                _ => {
-                    self.state.status = Status::Stopped;
-                    self.state.pid = None;
-                    self.store.save_state(&self.id, &self.state)?;
-                }
+        self.sync_stopped()
+    }
+
+    /// Reloads state.json and, if init is gone, gives the supervisor a moment
+    /// to record the exit status before falling back to a plain Stopped.
+    fn sync_stopped(&mut self) -> Result<()> {
+        self.state = self.store.load_state(&self.id)?;
+
+        if !self.state.status.is_live() {
+            return Ok(());
+        }
+        if self.state.pid.is_some_and(Self::proc_exists) {
+            return Ok(());
+        }
+
+        for _ in 0..100 {
+            if !self.state.supervisor_pid.is_some_and(Self::proc_exists) {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+
+            self.state = self.store.load_state(&self.id)?;
+            if !self.state.status.is_live() {
+                return Ok(());
             }
         }
+
+        // unless the supervisor got there first after all
+        self.state = self.store.update_state(&self.id, |state| {
+            if state.status.is_live() {
+                state.status = Status::Stopped;
+                state.pid = None;
+                state.supervisor_pid = None;
+            }
+            Ok(())
+        })?;
         Ok(())
     }
 
-    fn setns_file(path: &std::path::Path, nstype: nix::sched::CloneFlags) -> io::Result<()> {
-        let f = File::open(path)?;
+    /// Finds `prog` inside the rootfs the way execvp would inside the
+    /// container, searching the spec's PATH for bare names, and returns the
+    /// path to exec once the rootfs is "/".
+    fn resolve_executable(&self, prog: &str) -> Result<PathBuf> {
+        let rootfs = &self.spec.rootfs;
+
+        let candidates: Vec<PathBuf> = if prog.contains('/') {
+            let cwd = self.spec.cwd.clone().unwrap_or_else(|| PathBuf::from("/"));
+            vec![cwd.join(prog)]
+        } else {
+            let path = self
+                .spec
+                .env
+                .iter()
+                .find(|(k, _)| k == "PATH")
+                .map(|(_, v)| v.as_str())
+                .unwrap_or(DEFAULT_PATH);
+            path.split(':')
+                .filter(|d| !d.is_empty())
+                .map(|d| Path::new(d).join(prog))
+                .collect()
+        };
+
+        for c in &candidates {
+            let rel = c.strip_prefix("/").unwrap_or(c);
+            if rootfs.join(rel).is_file() {
+                return Ok(c.clone());
+            }
+        }
+
+        Err(RuntimeError::Msg(format!(
+            "executable not found in rootfs: {prog} (rootfs {})",
+            rootfs.display()
+        )))
+    }
+
+    fn setns_file(f: &File, nstype: nix::sched::CloneFlags) -> io::Result<()> {
         nix::sched::setns(f.as_fd(), nstype).map_err(nix_to_io)?;
         Ok(())
     }
@@ -330,9 +822,7 @@ impl Container {
         &self.state
     }
 
-    fn signal_from_i32(sig: i32) -> Result<Option<nix::sys::signal::Signal>> {
-        use nix::sys::signal::Signal;
-
+    fn signal_from_i32(sig: i32) -> Result<Option<Signal>> {
         if sig == 0 {
             return Ok(None);
         }
@@ -346,3 +836,12 @@ impl Container {
         std::path::Path::new("/proc").join(pid.to_string()).exists()
     }
 }
+
+/// Written like state.json (tmp + rename): engines poll for this file and
+/// must never read it half-written.
+fn write_pid_file(path: &Path, pid: i32) -> Result<()> {
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, pid.to_string())?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
@@ -0,0 +1,112 @@
+use super::oci;
+use crate::linux::isolation::nix_to_io;
+use crate::runtime::spec::{Hook, HookStage, Hooks};
+use crate::runtime::{Result, RuntimeError};
+use nix::fcntl::{FcntlArg, OFlag, fcntl};
+use std::io::{self, Write};
+use std::os::fd::AsFd;
+use std::os::unix::process::CommandExt;
+use std::process::{Command, ExitStatus, Stdio};
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Runs the hooks of one stage in order, stopping at the first failure.
+pub fn run(stage: HookStage, hooks: &Hooks, state: &oci::State) -> Result<()> {
+    let hooks = hooks.stage(stage);
+    if hooks.is_empty() {
+        return Ok(());
+    }
+
+    let input = serde_json::to_vec(state).map_err(|e| RuntimeError::Msg(e.to_string()))?;
+    for hook in hooks {
+        run_one(hook, &input).map_err(|e| {
+            RuntimeError::Msg(format!(
+                "{} hook {}: {e}",
+                stage.name(),
+                hook.path.display()
+            ))
+        })?;
+    }
+    Ok(())
+}
+
+/// For the stages whose failures must not undo the container (poststart,
+/// poststop): report and carry on.
+pub fn run_and_warn(stage: HookStage, hooks: &Hooks, state: &oci::State) {
+    if let Err(e) = run(stage, hooks, state) {
+        eprintln!("warning: {e}");
+    }
+}
+
+fn run_one(hook: &Hook, input: &[u8]) -> Result<()> {
+    let mut cmd = Command::new(&hook.path);
+    if let Some((arg0, rest)) = hook.args.split_first() {
+        cmd.arg0(arg0).args(rest);
+    }
+
+    cmd.env_clear();
+    for entry in &hook.env {
+        if let Some((k, v)) = entry.split_once('=') {
+            cmd.env(k, v);
+        }
+    }
+
+    // stdout may be the container's (create), so hook output goes to stderr
+    let stderr = io::stderr().as_fd().try_clone_to_owned()?;
+    cmd.stdin(Stdio::piped())
+        .stdout(Stdio::from(stderr.try_clone()?))
+        .stderr(Stdio::from(stderr));
+
+    let mut child = cmd.spawn()?;
+    // fed from the loop below rather than up front, so that a hook that
+    // never reads its input still runs into the timeout
+    let mut stdin = child.stdin.take();
+    if let Some(pipe) = &stdin {
+        fcntl(pipe, FcntlArg::F_SETFL(OFlag::O_NONBLOCK)).map_err(nix_to_io)?;
+    }
+    let mut pending = input;
+
+    let deadline = hook
+        .timeout
+        .map(|secs| Instant::now() + Duration::from_secs(secs));
+
+    let status = loop {
+        if let Some(pipe) = &mut stdin {
+            match pipe.write(pending) {
+                Ok(n) => pending = &pending[n..],
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                // a hook that ignores its input may well have exited already
+                Err(e) if e.kind() == io::ErrorKind::BrokenPipe => pending = &[],
+                Err(e) => return Err(e.into()),
+            }
+            if pending.is_empty() {
+                // EOF for the hook
+                stdin = None;
+            }
+        }
+
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if deadline.is_some_and(|d| Instant::now() >= d) {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(RuntimeError::Msg(format!(
+                "timed out after {}s",
+                hook.timeout.unwrap_or_default()
+            )));
+        }
+        thread::sleep(Duration::from_millis(10));
+    };
+
+    check_status(status)
+}
+
+fn check_status(status: ExitStatus) -> Result<()> {
+    if status.success() {
+        return Ok(());
+    }
+    Err(RuntimeError::Msg(format!("failed with {status}")))
+}
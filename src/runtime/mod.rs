@@ -1,8 +1,13 @@
 pub mod container;
 pub mod error;
+pub mod hooks;
+pub mod network;
+pub mod oci;
 pub mod spec;
 pub mod state;
 pub mod store;
+pub mod supervisor;
 pub mod unix;
+pub mod volume;
 
 pub use error::{Result, RuntimeError};
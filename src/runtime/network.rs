@@ -0,0 +1,203 @@
+use crate::linux::isolation::{enter_network_namespace, nix_to_io};
+use crate::linux::netlink::{Netlink, link_index};
+use crate::runtime::store::{Store, read_json, write_json};
+use crate::runtime::{Result, RuntimeError};
+use nix::fcntl::{Flock, FlockArg};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::net::Ipv4Addr;
+use std::os::fd::AsFd;
+
+pub const BRIDGE_NAME: &str = "podrun0";
+const SUBNET: Ipv4Addr = Ipv4Addr::new(10, 88, 0, 0);
+const PREFIX_LEN: u8 = 16;
+const GATEWAY: Ipv4Addr = Ipv4Addr::new(10, 88, 0, 1);
+
+/// A container's place on the podrun0 bridge, recorded in state.json.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Attachment {
+    pub bridge: String,
+    pub host_veth: String,
+    pub address: Ipv4Addr,
+    pub prefix_len: u8,
+    pub gateway: Ipv4Addr,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct Allocations {
+    by_id: BTreeMap<String, Ipv4Addr>,
+    /// Host veth names, kept unique here since they are only a hash of the id
+    #[serde(default)]
+    veths: BTreeMap<String, String>,
+}
+
+/// Reserves an address for `id`, reusing an earlier reservation if there is one.
+pub fn allocate(store: &Store, id: &str) -> Result<Attachment> {
+    let (_lock, path) = lock_ipam(store)?;
+    let mut allocs: Allocations = if path.exists() {
+        read_json(path.clone())?
+    } else {
+        Allocations::default()
+    };
+    // written before veth names were recorded, when they were never salted
+    for other in allocs.by_id.keys() {
+        if !allocs.veths.contains_key(other) {
+            allocs.veths.insert(other.clone(), host_veth_name(other, 0));
+        }
+    }
+
+    let address = match allocs.by_id.get(id) {
+        Some(addr) => *addr,
+        None => {
+            let addr = first_free(&allocs)?;
+            allocs.by_id.insert(id.to_string(), addr);
+            addr
+        }
+    };
+    let host_veth = match allocs.veths.get(id) {
+        Some(name) => name.clone(),
+        None => {
+            let name = free_veth_name(&allocs, id)?;
+            allocs.veths.insert(id.to_string(), name.clone());
+            name
+        }
+    };
+    write_json(path, &allocs)?;
+
+    Ok(Attachment {
+        bridge: BRIDGE_NAME.to_string(),
+        host_veth,
+        address,
+        prefix_len: PREFIX_LEN,
+        gateway: GATEWAY,
+    })
+}
+
+pub fn release(store: &Store, id: &str) -> Result<()> {
+    let (_lock, path) = lock_ipam(store)?;
+    if !path.exists() {
+        return Ok(());
+    }
+
+    let mut allocs: Allocations = read_json(path.clone())?;
+    let removed = allocs.by_id.remove(id).is_some();
+    if allocs.veths.remove(id).is_some() || removed {
+        write_json(path, &allocs)?;
+    }
+    Ok(())
+}
+
+/// Runs in the supervisor before init is spawned: wires a veth pair between
+/// podrun0 and a new network namespace, then moves the supervisor into that
+/// namespace so init inherits it fully configured.
+pub fn enter_bridged_namespace(att: &Attachment) -> Result<()> {
+    let peer = format!("{}p", att.host_veth);
+
+    let mut host = Netlink::open()?;
+    host.create_bridge(&att.bridge)?;
+    let bridge = link_index(&att.bridge)?;
+    host.add_address(bridge, att.gateway, att.prefix_len)?;
+    host.set_link_up(bridge)?;
+
+    // a leftover of this id's own earlier allocation, from a container that
+    // was killed without `delete`: `allocate` hands out no name in use otherwise
+    if let Ok(stale) = link_index(&att.host_veth) {
+        host.delete_link(stale)?;
+    }
+
+    host.create_veth(&att.host_veth, &peer)?;
+    let veth = link_index(&att.host_veth)?;
+    host.set_link_master(veth, bridge)?;
+    host.set_link_up(veth)?;
+
+    let peer_index = link_index(&peer)?;
+    enter_network_namespace()?;
+
+    // `host` was opened before unsharing, so it still talks to the host stack
+    let netns = File::open("/proc/self/ns/net")?;
+    host.set_link_netns(peer_index, netns.as_fd())?;
+
+    let mut ns = Netlink::open()?;
+    ns.set_link_up(link_index("lo")?)?;
+    let eth = link_index(&peer)?;
+    ns.set_link_name(eth, "eth0")?;
+    ns.add_address(eth, att.address, att.prefix_len)?;
+    ns.set_link_up(eth)?;
+    ns.add_default_route(att.gateway)?;
+
+    Ok(())
+}
+
+/// Removes the host end of the veth pair; the kernel drops the peer with it.
+pub fn detach(att: &Attachment) -> Result<()> {
+    if let Ok(index) = link_index(&att.host_veth) {
+        Netlink::open()?.delete_link(index)?;
+    }
+    Ok(())
+}
+
+fn lock_ipam(store: &Store) -> Result<(Flock<File>, std::path::PathBuf)> {
+    let dir = store.network_dir();
+    fs::create_dir_all(&dir)?;
+
+    let lock = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(dir.join("ipam.lock"))?;
+    let lock = Flock::lock(lock, FlockArg::LockExclusive).map_err(|(_, e)| nix_to_io(e))?;
+
+    Ok((lock, dir.join("ipam.json")))
+}
+
+fn first_free(allocs: &Allocations) -> Result<Ipv4Addr> {
+    let base = u32::from(SUBNET);
+    let size = 1u32 << (32 - PREFIX_LEN);
+
+    // skip the network address, the gateway and the broadcast address
+    (2..size - 1)
+        .map(|off| Ipv4Addr::from(base + off))
+        .find(|addr| !allocs.by_id.values().any(|a| a == addr))
+        .ok_or_else(|| {
+            RuntimeError::Msg(format!("no free addresses left in {SUBNET}/{PREFIX_LEN}"))
+        })
+}
+
+/// A name for `id`'s host veth that no other allocation uses and no existing
+/// link has, salting the hash until one fits.
+fn free_veth_name(allocs: &Allocations, id: &str) -> Result<String> {
+    (0..1000)
+        .map(|salt| host_veth_name(id, salt))
+        .find(|name| !allocs.veths.values().any(|v| v == name) && link_index(name).is_err())
+        .ok_or_else(|| RuntimeError::Msg(format!("no free veth name for container {id}")))
+}
+
+/// Interface names are capped at 15 bytes and ids are not, so hash the id.
+fn host_veth_name(id: &str, salt: u32) -> String {
+    let key = match salt {
+        0 => id.to_string(),
+        _ => format!("{id}#{salt}"),
+    };
+    // FNV-1a
+    let hash = key.bytes().fold(0x811c9dc5u32, |h, b| {
+        (h ^ b as u32).wrapping_mul(0x01000193)
+    });
+    format!("vpr{hash:08x}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colliding_veth_names_are_salted() {
+        let mut allocs = Allocations::default();
+        // as if "a" had hashed to the same name as "b"
+        allocs.veths.insert("a".into(), host_veth_name("b", 0));
+
+        let name = free_veth_name(&allocs, "b").unwrap();
+        assert_ne!(name, host_veth_name("b", 0));
+        assert_eq!(name, host_veth_name("b", 1));
+    }
+}
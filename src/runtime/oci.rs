@@ -0,0 +1,547 @@
+//! OCI runtime-spec `config.json` support. Only the fields podrun can honor
+//! are modeled; anything else is rejected by serde (`deny_unknown_fields`)
+//! instead of being silently ignored.
+
+use crate::linux::{capabilities, process::rlimit_resource};
+use crate::runtime::spec::{
+    Capabilities, DeviceKind, DeviceRule, Hook, Hooks, IdMapping, Mount, Namespaces, NetworkMode,
+    Resources, Rlimit, Seccomp, Spec, User,
+};
+use crate::runtime::{Result, RuntimeError};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const OCI_VERSION: &str = "1.0.2";
+
+/// The runtime-spec state, with the extra fields runc reports and that
+/// engines such as containerd read back (`rootfs`, `created`). Printed by
+/// `state` and fed to hooks on stdin.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct State {
+    pub oci_version: &'static str,
+    pub id: String,
+    pub status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pid: Option<i32>,
+    pub bundle: PathBuf,
+    pub rootfs: PathBuf,
+    pub created: String,
+    pub annotations: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct Config {
+    oci_version: String,
+    root: Root,
+    process: Process,
+    hostname: Option<String>,
+    #[serde(default)]
+    mounts: Vec<OciMount>,
+    /// Free-form metadata, reported back by `state`.
+    #[serde(default)]
+    annotations: BTreeMap<String, String>,
+    hooks: Option<OciHooks>,
+    linux: Option<Linux>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct OciHooks {
+    #[serde(default)]
+    prestart: Vec<OciHook>,
+    #[serde(default)]
+    create_runtime: Vec<OciHook>,
+    /// Would have to run inside the container's namespaces; not supported.
+    create_container: Option<Vec<serde_json::Value>>,
+    start_container: Option<Vec<serde_json::Value>>,
+    #[serde(default)]
+    poststart: Vec<OciHook>,
+    #[serde(default)]
+    poststop: Vec<OciHook>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct OciHook {
+    path: PathBuf,
+    #[serde(default)]
+    args: Vec<String>,
+    #[serde(default)]
+    env: Vec<String>,
+    timeout: Option<i64>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct Root {
+    path: PathBuf,
+    #[serde(default)]
+    readonly: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct Process {
+    #[serde(default)]
+    terminal: bool,
+    user: OciUser,
+    args: Vec<String>,
+    #[serde(default)]
+    env: Vec<String>,
+    cwd: PathBuf,
+    capabilities: Option<OciCapabilities>,
+    #[serde(default)]
+    rlimits: Vec<OciRlimit>,
+    #[serde(default)]
+    no_new_privileges: bool,
+    oom_score_adj: Option<i32>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct OciUser {
+    uid: u32,
+    gid: u32,
+    #[serde(default)]
+    additional_gids: Vec<u32>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct OciCapabilities {
+    bounding: Vec<String>,
+    effective: Vec<String>,
+    inheritable: Vec<String>,
+    permitted: Vec<String>,
+    ambient: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct OciRlimit {
+    #[serde(rename = "type")]
+    kind: String,
+    hard: u64,
+    soft: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct OciMount {
+    destination: PathBuf,
+    #[serde(rename = "type")]
+    kind: Option<String>,
+    source: Option<PathBuf>,
+    #[serde(default)]
+    options: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct Linux {
+    #[serde(default)]
+    namespaces: Vec<OciNamespace>,
+    #[serde(default)]
+    uid_mappings: Vec<OciIdMapping>,
+    #[serde(default)]
+    gid_mappings: Vec<OciIdMapping>,
+    resources: Option<OciResources>,
+    /// Same format as `--seccomp-profile` files
+    seccomp: Option<Seccomp>,
+    /// Absent means podrun's defaults, present (even empty) replaces them
+    masked_paths: Option<Vec<PathBuf>>,
+    readonly_paths: Option<Vec<PathBuf>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct OciIdMapping {
+    #[serde(rename = "containerID")]
+    container_id: u32,
+    #[serde(rename = "hostID")]
+    host_id: u32,
+    size: u32,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct OciNamespace {
+    #[serde(rename = "type")]
+    kind: String,
+    path: Option<PathBuf>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct OciResources {
+    memory: Option<OciMemory>,
+    cpu: Option<OciCpu>,
+    pids: Option<OciPids>,
+    #[serde(default)]
+    devices: Vec<OciDeviceRule>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct OciDeviceRule {
+    allow: bool,
+    #[serde(rename = "type")]
+    kind: Option<String>,
+    major: Option<i64>,
+    minor: Option<i64>,
+    access: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct OciMemory {
+    limit: Option<i64>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct OciCpu {
+    quota: Option<i64>,
+    period: Option<u64>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct OciPids {
+    limit: i64,
+}
+
+/// Reads `<bundle>/config.json` and turns it into a podrun `Spec`.
+pub fn load_bundle(bundle: &Path) -> Result<Spec> {
+    let path = bundle.join("config.json");
+    let raw = fs::read_to_string(&path)
+        .map_err(|e| RuntimeError::Msg(format!("reading {}: {e}", path.display())))?;
+    let cfg: Config = serde_json::from_str(&raw)
+        .map_err(|e| RuntimeError::Msg(format!("{}: {e}", path.display())))?;
+
+    if !cfg.oci_version.starts_with("1.") {
+        return Err(unsupported(format!("ociVersion {}", cfg.oci_version)));
+    }
+    if !cfg.process.cwd.is_absolute() {
+        return Err(RuntimeError::Msg(format!(
+            "config.json: process.cwd must be absolute, got {}",
+            cfg.process.cwd.display()
+        )));
+    }
+
+    let rootfs = if cfg.root.path.is_absolute() {
+        cfg.root.path.clone()
+    } else {
+        bundle.join(&cfg.root.path)
+    };
+
+    let mut spec = Spec::new(rootfs, cfg.process.args.clone());
+    spec.readonly_rootfs = cfg.root.readonly;
+    spec.env = parse_env(&cfg.process.env)?;
+    spec.cwd = Some(cfg.process.cwd.clone());
+    spec.hostname = cfg.hostname.clone();
+    spec.terminal = cfg.process.terminal;
+    spec.annotations = cfg.annotations.clone();
+    spec.user = Some(User {
+        uid: cfg.process.user.uid,
+        gid: cfg.process.user.gid,
+        additional_gids: cfg.process.user.additional_gids.clone(),
+    });
+
+    if let Some(caps) = &cfg.process.capabilities {
+        let caps = Capabilities {
+            bounding: caps.bounding.clone(),
+            effective: caps.effective.clone(),
+            inheritable: caps.inheritable.clone(),
+            permitted: caps.permitted.clone(),
+            ambient: caps.ambient.clone(),
+        };
+        capabilities::validate(&caps)?;
+        spec.capabilities = Some(caps);
+    }
+
+    spec.no_new_privileges = cfg.process.no_new_privileges;
+    spec.oom_score_adj = cfg.process.oom_score_adj;
+
+    for rl in &cfg.process.rlimits {
+        rlimit_resource(&rl.kind)?;
+        spec.rlimits.push(Rlimit {
+            kind: rl.kind.clone(),
+            soft: rl.soft,
+            hard: rl.hard,
+        });
+    }
+
+    spec.mounts = cfg
+        .mounts
+        .iter()
+        .map(|m| convert_mount(bundle, m))
+        .collect::<Result<_>>()?;
+
+    if let Some(hooks) = &cfg.hooks {
+        spec.hooks = convert_hooks(hooks)?;
+    }
+
+    let linux = cfg.linux.as_ref();
+    let (network, namespaces) =
+        convert_namespaces(linux.map(|l| &l.namespaces[..]).unwrap_or(&[]))?;
+    spec.network = network;
+    spec.namespaces = namespaces;
+
+    if let Some(linux) = linux {
+        let convert = |maps: &[OciIdMapping]| -> Vec<IdMapping> {
+            maps.iter()
+                .map(|m| IdMapping {
+                    container_id: m.container_id,
+                    host_id: m.host_id,
+                    size: m.size,
+                })
+                .collect()
+        };
+        spec.uid_mappings = convert(&linux.uid_mappings);
+        spec.gid_mappings = convert(&linux.gid_mappings);
+    }
+    let has_mappings = !spec.uid_mappings.is_empty() || !spec.gid_mappings.is_empty();
+    if has_mappings && !spec.namespaces.user {
+        return Err(RuntimeError::Msg(
+            "config.json: uid/gid mappings need a user namespace".into(),
+        ));
+    }
+
+    spec.seccomp = linux.and_then(|l| l.seccomp.clone());
+
+    if let Some(paths) = linux.and_then(|l| l.masked_paths.clone()) {
+        spec.masked_paths = paths;
+    }
+    if let Some(paths) = linux.and_then(|l| l.readonly_paths.clone()) {
+        spec.readonly_paths = paths;
+    }
+
+    if let Some(res) = linux.and_then(|l| l.resources.as_ref()) {
+        spec.resources = convert_resources(res)?;
+    }
+
+    Ok(spec)
+}
+
+fn convert_hooks(hooks: &OciHooks) -> Result<Hooks> {
+    if hooks
+        .create_container
+        .as_ref()
+        .is_some_and(|h| !h.is_empty())
+    {
+        return Err(unsupported("hooks.createContainer".into()));
+    }
+    if hooks
+        .start_container
+        .as_ref()
+        .is_some_and(|h| !h.is_empty())
+    {
+        return Err(unsupported("hooks.startContainer".into()));
+    }
+
+    let convert = |list: &[OciHook]| -> Result<Vec<Hook>> {
+        list.iter()
+            .map(|h| {
+                if !h.path.is_absolute() {
+                    return Err(RuntimeError::Msg(format!(
+                        "config.json: hook path must be absolute, got {}",
+                        h.path.display()
+                    )));
+                }
+                let timeout = match h.timeout {
+                    Some(t) if t <= 0 => {
+                        return Err(RuntimeError::Msg(format!(
+                            "config.json: hook timeout must be positive, got {t}"
+                        )));
+                    }
+                    t => t.map(|t| t as u64),
+                };
+                Ok(Hook {
+                    path: h.path.clone(),
+                    args: h.args.clone(),
+                    env: h.env.clone(),
+                    timeout,
+                })
+            })
+            .collect()
+    };
+
+    Ok(Hooks {
+        prestart: convert(&hooks.prestart)?,
+        create_runtime: convert(&hooks.create_runtime)?,
+        poststart: convert(&hooks.poststart)?,
+        poststop: convert(&hooks.poststop)?,
+    })
+}
+
+fn convert_device_rule(rule: &OciDeviceRule) -> Result<DeviceRule> {
+    let kind = match rule.kind.as_deref() {
+        None | Some("a") => None,
+        Some("c") => Some(DeviceKind::Char),
+        Some("b") => Some(DeviceKind::Block),
+        Some(other) => return Err(unsupported(format!("device rule type {other}"))),
+    };
+
+    // omitted or -1 both mean "any"
+    let number = |n: Option<i64>| -> Result<Option<u32>> {
+        match n {
+            None | Some(-1) => Ok(None),
+            Some(n) => u32::try_from(n).map(Some).map_err(|_| {
+                RuntimeError::Msg(format!(
+                    "invalid device number {n} in linux.resources.devices"
+                ))
+            }),
+        }
+    };
+
+    let access = match rule.access.as_deref() {
+        None | Some("") => "rwm".to_string(),
+        Some(a) if a.chars().all(|c| matches!(c, 'r' | 'w' | 'm')) => a.to_string(),
+        Some(a) => {
+            return Err(RuntimeError::Msg(format!(
+                "invalid device access '{a}' in linux.resources.devices"
+            )));
+        }
+    };
+
+    Ok(DeviceRule {
+        allow: rule.allow,
+        kind,
+        major: number(rule.major)?,
+        minor: number(rule.minor)?,
+        access,
+    })
+}
+
+fn unsupported(what: String) -> RuntimeError {
+    RuntimeError::Msg(format!("config.json: {what} is not supported by podrun"))
+}
+
+fn parse_env(items: &[String]) -> Result<Vec<(String, String)>> {
+    items
+        .iter()
+        .map(|s| {
+            s.split_once('=')
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .ok_or_else(|| {
+                    RuntimeError::Msg(format!(
+                        "config.json: invalid process.env entry '{s}', expected KEY=VALUE"
+                    ))
+                })
+        })
+        .collect()
+}
+
+fn convert_mount(bundle: &Path, m: &OciMount) -> Result<Mount> {
+    if !m.destination.is_absolute() {
+        return Err(RuntimeError::Msg(format!(
+            "config.json: mount destination must be absolute, got {}",
+            m.destination.display()
+        )));
+    }
+
+    let is_bind =
+        m.kind.as_deref() == Some("bind") || m.options.iter().any(|o| o == "bind" || o == "rbind");
+
+    // bind sources are host paths, relative ones are relative to the bundle
+    let source = match &m.source {
+        Some(src) if is_bind && !src.is_absolute() => Some(bundle.join(src)),
+        other => other.clone(),
+    };
+
+    let kind = match &m.kind {
+        Some(kind) => kind.clone(),
+        None if is_bind => "bind".into(),
+        None => {
+            return Err(RuntimeError::Msg(format!(
+                "config.json: mount at {} has no type and isn't a bind mount",
+                m.destination.display()
+            )));
+        }
+    };
+
+    Ok(Mount {
+        destination: m.destination.clone(),
+        kind,
+        source,
+        options: m.options.clone(),
+    })
+}
+
+/// OCI lists every namespace explicitly; absence means "share the host's".
+fn convert_namespaces(list: &[OciNamespace]) -> Result<(NetworkMode, Namespaces)> {
+    let mut ns = Namespaces {
+        user: false,
+        pid: false,
+        ipc: false,
+        cgroup: false,
+        network_path: None,
+    };
+    let mut network = NetworkMode::Host;
+    let mut has_mount = false;
+
+    for entry in list {
+        if entry.path.is_some() && entry.kind != "network" {
+            return Err(unsupported(format!(
+                "joining an existing {} namespace",
+                entry.kind
+            )));
+        }
+
+        match entry.kind.as_str() {
+            "mount" => has_mount = true,
+            // podrun always gives the container its own UTS namespace
+            "uts" => {}
+            "user" => ns.user = true,
+            "pid" => ns.pid = true,
+            "ipc" => ns.ipc = true,
+            "cgroup" => ns.cgroup = true,
+            "network" => match &entry.path {
+                Some(path) => ns.network_path = Some(path.clone()),
+                None => network = NetworkMode::None,
+            },
+            other => return Err(unsupported(format!("linux.namespaces type {other}"))),
+        }
+    }
+
+    if !has_mount {
+        return Err(unsupported("running without a mount namespace".into()));
+    }
+
+    Ok((network, ns))
+}
+
+fn convert_resources(res: &OciResources) -> Result<Resources> {
+    let mut out = Resources::default();
+
+    if let Some(limit) = res.memory.as_ref().and_then(|m| m.limit) {
+        // -1 is "unlimited" in the runtime-spec
+        if limit > 0 {
+            out.memory = Some(limit as u64);
+        }
+    }
+
+    if let Some(cpu) = &res.cpu
+        && let Some(quota) = cpu.quota.filter(|q| *q > 0)
+    {
+        let period = cpu.period.unwrap_or(100_000).max(1);
+        out.cpus = Some(quota as f64 / period as f64);
+    }
+
+    if let Some(pids) = &res.pids
+        && pids.limit > 0
+    {
+        out.pids_limit = Some(pids.limit as u64);
+    }
+
+    for rule in &res.devices {
+        out.devices.push(convert_device_rule(rule)?);
+    }
+
+    Ok(out)
+}
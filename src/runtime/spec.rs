@@ -1,11 +1,470 @@
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Spec {
     pub rootfs: PathBuf,
+    /// Copy-on-write: `rootfs` is only the read-only lower layer of an
+    /// overlay whose upper layer lives (and dies) with the container
+    #[serde(default)]
+    pub overlay: bool,
+    /// Remount "/" read-only once setup is done
+    #[serde(default)]
+    pub readonly_rootfs: bool,
     pub argv: Vec<String>,
     pub env: Vec<(String, String)>,
     pub cwd: Option<PathBuf>,
     pub hostname: Option<String>,
+    #[serde(default)]
+    pub network: NetworkMode,
+    #[serde(default)]
+    pub resources: Resources,
+    #[serde(default)]
+    pub namespaces: Namespaces,
+    pub user: Option<User>,
+    #[serde(default)]
+    pub mounts: Vec<Mount>,
+    /// RAM-backed scratch mounts, from `--tmpfs`
+    #[serde(default)]
+    pub tmpfs: Vec<Tmpfs>,
+    /// Host devices passed in with `--device`
+    #[serde(default)]
+    pub devices: Vec<Device>,
+    /// Hidden from the container: /dev/null over files, an empty read-only
+    /// tmpfs over directories. Missing paths are skipped.
+    #[serde(default)]
+    pub masked_paths: Vec<PathBuf>,
+    /// Bound read-only onto themselves; missing paths are skipped
+    #[serde(default)]
+    pub readonly_paths: Vec<PathBuf>,
+    /// Named volumes behind some of `mounts`, released again on delete
+    #[serde(default)]
+    pub volumes: Vec<String>,
+    /// `None` leaves the capability sets of the (root) caller untouched
+    pub capabilities: Option<Capabilities>,
+    /// `None` runs without a syscall filter
+    #[serde(default)]
+    pub seccomp: Option<Seccomp>,
+    #[serde(default)]
+    pub rlimits: Vec<Rlimit>,
+    /// Set no_new_privs, so setuid binaries and file capabilities can't
+    /// raise privileges past exec
+    #[serde(default)]
+    pub no_new_privileges: bool,
+    /// Written to /proc/self/oom_score_adj, -1000 (never kill) to 1000
+    #[serde(default)]
+    pub oom_score_adj: Option<i32>,
+    /// Give init a pseudo-terminal, handed to the caller's `--console-socket`
+    #[serde(default)]
+    pub terminal: bool,
+    #[serde(default)]
+    pub annotations: BTreeMap<String, String>,
+    #[serde(default)]
+    pub hooks: Hooks,
+    /// Only used with `namespaces.user`
+    #[serde(default)]
+    pub uid_mappings: Vec<IdMapping>,
+    #[serde(default)]
+    pub gid_mappings: Vec<IdMapping>,
+}
+
+impl Spec {
+    pub fn new(rootfs: PathBuf, argv: Vec<String>) -> Self {
+        Self {
+            rootfs,
+            overlay: false,
+            readonly_rootfs: false,
+            argv,
+            env: vec![],
+            cwd: None,
+            hostname: None,
+            network: NetworkMode::default(),
+            resources: Resources::default(),
+            namespaces: Namespaces::default(),
+            user: None,
+            mounts: vec![],
+            tmpfs: vec![],
+            devices: vec![],
+            masked_paths: DEFAULT_MASKED_PATHS.iter().map(PathBuf::from).collect(),
+            readonly_paths: DEFAULT_READONLY_PATHS.iter().map(PathBuf::from).collect(),
+            volumes: vec![],
+            capabilities: None,
+            seccomp: None,
+            rlimits: vec![],
+            no_new_privileges: false,
+            oom_score_adj: None,
+            terminal: false,
+            annotations: BTreeMap::new(),
+            hooks: Hooks::default(),
+            uid_mappings: vec![],
+            gid_mappings: vec![],
+        }
+    }
+
+    /// Whether a spec mount already covers `dest`, so the built-in one is skipped.
+    pub fn has_mount_at(&self, dest: &str) -> bool {
+        self.mounts.iter().any(|m| m.destination == Path::new(dest))
+            || self.tmpfs.iter().any(|t| t.destination == Path::new(dest))
+    }
+
+    /// Adds `rlimits`, each replacing one of the same type already set.
+    pub fn override_rlimits(&mut self, rlimits: Vec<Rlimit>) {
+        for rl in rlimits {
+            self.rlimits
+                .retain(|r| !r.kind.eq_ignore_ascii_case(&rl.kind));
+            self.rlimits.push(rl);
+        }
+    }
+}
+
+pub const DEFAULT_PATH: &str = "/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin";
+
+/// Kernel interfaces that leak host information or memory (docker's list).
+pub const DEFAULT_MASKED_PATHS: &[&str] = &[
+    "/proc/acpi",
+    "/proc/asound",
+    "/proc/interrupts",
+    "/proc/kcore",
+    "/proc/keys",
+    "/proc/latency_stats",
+    "/proc/sched_debug",
+    "/proc/scsi",
+    "/proc/timer_list",
+    "/proc/timer_stats",
+    "/sys/devices/virtual/powercap",
+    "/sys/firmware",
+];
+
+/// Kernel settings the container may read but not change.
+pub const DEFAULT_READONLY_PATHS: &[&str] = &[
+    "/proc/bus",
+    "/proc/fs",
+    "/proc/irq",
+    "/proc/sys",
+    "/proc/sysrq-trigger",
+];
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum NetworkMode {
+    /// Own network namespace with only loopback up
+    #[default]
+    None,
+    /// Share the host network stack
+    Host,
+    /// Own network namespace attached to the podrun0 bridge
+    Bridge,
+}
+
+/// cgroup v2 limits; `None` leaves the kernel default (unlimited).
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct Resources {
+    /// memory.max, in bytes
+    pub memory: Option<u64>,
+    /// cpu.max, as a number of CPUs (1.5 = 150ms every 100ms)
+    pub cpus: Option<f64>,
+    /// pids.max
+    pub pids_limit: Option<u64>,
+    /// From OCI `linux.resources.devices`; the default allow-list and
+    /// `--device` entries are added on top when the policy is built
+    #[serde(default)]
+    pub devices: Vec<DeviceRule>,
+}
+
+impl Resources {
+    /// Whether no limits are set. Device rules don't count: they need a
+    /// root-loaded eBPF program, so they're only enforced as root.
+    pub fn is_empty(&self) -> bool {
+        self.memory.is_none() && self.cpus.is_none() && self.pids_limit.is_none()
+    }
+}
+
+/// Namespaces beyond the mount and UTS ones every container gets. The network
+/// namespace is controlled by `Spec::network` unless an existing one is joined.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Namespaces {
+    /// Entered first, so every other namespace is owned by it
+    pub user: bool,
+    pub pid: bool,
+    pub ipc: bool,
+    pub cgroup: bool,
+    /// Join this network namespace (e.g. /var/run/netns/foo) instead
+    pub network_path: Option<PathBuf>,
+}
+
+impl Default for Namespaces {
+    fn default() -> Self {
+        Self {
+            user: false,
+            pid: true,
+            ipc: false,
+            cgroup: false,
+            network_path: None,
+        }
+    }
+}
+
+/// One line of /proc/<pid>/uid_map (or gid_map).
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct IdMapping {
+    pub container_id: u32,
+    pub host_id: u32,
+    pub size: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct User {
+    pub uid: u32,
+    pub gid: u32,
+    #[serde(default)]
+    pub additional_gids: Vec<u32>,
+}
+
+/// A mount performed inside the rootfs before `pivot_root`, in OCI terms:
+/// `options` are the usual mount(8) flags plus filesystem data.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Mount {
+    pub destination: PathBuf,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub source: Option<PathBuf>,
+    #[serde(default)]
+    pub options: Vec<String>,
+}
+
+/// `options` are mount flags (ro, noexec, ...) and tmpfs data such as
+/// `size=64m` or `mode=1777`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Tmpfs {
+    pub destination: PathBuf,
+    #[serde(default)]
+    pub options: Vec<String>,
+}
+
+/// A host device node recreated in the container's /dev, numbers and mode
+/// as they were on the host at create time.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Device {
+    pub path: PathBuf,
+    /// Bind-mounted instead of calling mknod in a user namespace
+    pub host_path: PathBuf,
+    #[serde(rename = "type")]
+    pub kind: DeviceKind,
+    pub major: u64,
+    pub minor: u64,
+    pub file_mode: u32,
+    /// What the cgroup device policy allows: some of `r`, `w` and `m`
+    pub access: String,
+}
+
+/// One cgroup device policy rule; the last rule matching an access decides,
+/// and an access no rule matches is denied.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DeviceRule {
+    pub allow: bool,
+    /// `None` for both kinds
+    #[serde(rename = "type")]
+    pub kind: Option<DeviceKind>,
+    /// `None` for any
+    pub major: Option<u32>,
+    pub minor: Option<u32>,
+    /// Some of `r`, `w` and `m`
+    pub access: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceKind {
+    #[serde(rename = "c")]
+    Char,
+    #[serde(rename = "b")]
+    Block,
+}
+
+/// Capability names as in capabilities(7), e.g. `CAP_NET_BIND_SERVICE`.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct Capabilities {
+    pub bounding: Vec<String>,
+    pub effective: Vec<String>,
+    pub inheritable: Vec<String>,
+    pub permitted: Vec<String>,
+    pub ambient: Vec<String>,
+}
+
+/// A seccomp profile, in the format shared by Docker's profile files and
+/// OCI `linux.seccomp`. Actions are libseccomp names (`SCMP_ACT_ERRNO`),
+/// and the first rule matching a syscall decides.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Seccomp {
+    pub default_action: String,
+    /// errno for `SCMP_ACT_ERRNO` without one; EPERM if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_errno_ret: Option<u32>,
+    /// Only the native architecture is filtered; syscalls made through any
+    /// other ABI (32-bit x86, x32) kill the process whatever this says
+    #[serde(default)]
+    pub architectures: Vec<String>,
+    /// Docker's form of `architectures`, kept only so its files load
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub arch_map: Vec<serde_json::Value>,
+    /// e.g. `SECCOMP_FILTER_FLAG_LOG`
+    #[serde(default)]
+    pub flags: Vec<String>,
+    #[serde(default)]
+    pub syscalls: Vec<SyscallRule>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct SyscallRule {
+    pub names: Vec<String>,
+    pub action: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub errno_ret: Option<u32>,
+    /// All must hold for the rule to match
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub args: Vec<SyscallArg>,
+    /// Docker extensions: the rule only applies to containers that have
+    /// (or lack) these capabilities, architectures or kernel version
+    #[serde(default, skip_serializing_if = "SyscallCondition::is_empty")]
+    pub includes: SyscallCondition,
+    #[serde(default, skip_serializing_if = "SyscallCondition::is_empty")]
+    pub excludes: SyscallCondition,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+}
+
+/// A comparison of one syscall argument: `arg[index] <op> value`, or
+/// `arg[index] & value == value_two` for `SCMP_CMP_MASKED_EQ`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct SyscallArg {
+    pub index: u32,
+    pub value: u64,
+    #[serde(default)]
+    pub value_two: u64,
+    pub op: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase", default, deny_unknown_fields)]
+pub struct SyscallCondition {
+    /// e.g. `CAP_SYS_ADMIN`; includes needs all of them, excludes any
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub caps: Vec<String>,
+    /// Go architecture names, e.g. `amd64`, `arm64`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub arches: Vec<String>,
+    /// e.g. `4.8`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_kernel: Option<String>,
+}
+
+impl SyscallCondition {
+    pub fn is_empty(&self) -> bool {
+        self.caps.is_empty() && self.arches.is_empty() && self.min_kernel.is_none()
+    }
+}
+
+/// Docker's older profiles write `"args": null`.
+fn null_as_default<'de, D, T>(de: D) -> Result<T, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Default + Deserialize<'de>,
+{
+    Ok(Option::<T>::deserialize(de)?.unwrap_or_default())
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Rlimit {
+    /// e.g. `RLIMIT_NOFILE`
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub soft: u64,
+    pub hard: u64,
+}
+
+/// Host programs run at OCI lifecycle points, each fed the OCI state JSON on
+/// stdin.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Hooks {
+    /// `start`, before init is released; a failure kills the container
+    pub prestart: Vec<Hook>,
+    /// `create`, once init's namespaces exist; a failure aborts the create
+    pub create_runtime: Vec<Hook>,
+    /// `start`, after init is released; failures are only reported
+    pub poststart: Vec<Hook>,
+    /// `delete`, after the container is torn down; failures are only reported
+    pub poststop: Vec<Hook>,
+}
+
+impl Hooks {
+    pub fn stage(&self, stage: HookStage) -> &[Hook] {
+        match stage {
+            HookStage::Prestart => &self.prestart,
+            HookStage::CreateRuntime => &self.create_runtime,
+            HookStage::Poststart => &self.poststart,
+            HookStage::Poststop => &self.poststop,
+        }
+    }
+
+    pub fn stage_mut(&mut self, stage: HookStage) -> &mut Vec<Hook> {
+        match stage {
+            HookStage::Prestart => &mut self.prestart,
+            HookStage::CreateRuntime => &mut self.create_runtime,
+            HookStage::Poststart => &mut self.poststart,
+            HookStage::Poststop => &mut self.poststop,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookStage {
+    Prestart,
+    CreateRuntime,
+    Poststart,
+    Poststop,
+}
+
+impl HookStage {
+    /// The stage's name in config.json
+    pub fn name(self) -> &'static str {
+        match self {
+            HookStage::Prestart => "prestart",
+            HookStage::CreateRuntime => "createRuntime",
+            HookStage::Poststart => "poststart",
+            HookStage::Poststop => "poststop",
+        }
+    }
+}
+
+impl std::str::FromStr for HookStage {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "prestart" => Ok(HookStage::Prestart),
+            "createRuntime" | "create-runtime" => Ok(HookStage::CreateRuntime),
+            "poststart" => Ok(HookStage::Poststart),
+            "poststop" => Ok(HookStage::Poststop),
+            other => Err(format!(
+                "unknown hook stage '{other}', expected prestart, createRuntime, poststart or poststop"
+            )),
+        }
+    }
+}
+
+/// An OCI hook: `args` includes argv[0], `env` replaces the environment.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Hook {
+    pub path: PathBuf,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub env: Vec<String>,
+    /// Seconds before the hook is killed and counted as failed
+    pub timeout: Option<u64>,
 }
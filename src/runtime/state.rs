@@ -1,20 +1,48 @@
+use super::network::Attachment;
 use super::unix::now_unix;
 use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
 
-#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
 pub enum Status {
     Created,
     Running,
+    Paused,
     Stopped,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl Status {
+    /// Status as spelled in the OCI runtime-spec state (plus runc's "paused").
+    pub fn oci_name(self) -> &'static str {
+        match self {
+            Status::Created => "created",
+            Status::Running => "running",
+            Status::Paused => "paused",
+            Status::Stopped => "stopped",
+        }
+    }
+
+    /// Init may exist and has not been reaped: parked on the exec FIFO
+    /// (Created), running, or frozen.
+    pub fn is_live(self) -> bool {
+        matches!(self, Status::Created | Status::Running | Status::Paused)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct State {
     pub id: String,
     pub status: Status,
     pub pid: Option<i32>,
     pub created_at_unix: u64,
     pub started_at_unix: Option<u64>,
+    pub supervisor_pid: Option<i32>,
+    pub exit_code: Option<i32>,
+    pub exit_signal: Option<i32>,
+    pub finished_at_unix: Option<u64>,
+    pub network: Option<Attachment>,
+    /// OCI bundle the container was created from, if any
+    pub bundle: Option<PathBuf>,
 }
 
 impl State {
@@ -25,6 +53,12 @@ impl State {
             pid: None,
             created_at_unix: now_unix(),
             started_at_unix: None,
+            supervisor_pid: None,
+            exit_code: None,
+            exit_signal: None,
+            finished_at_unix: None,
+            network: None,
+            bundle: None,
         }
     }
 }
@@ -1,49 +1,108 @@
+use crate::linux::isolation::nix_to_io;
 use crate::runtime::{Result, spec::Spec};
 use crate::runtime::state::State;
+use nix::fcntl::{Flock, FlockArg};
+use nix::unistd::Uid;
+use std::fs::{File, OpenOptions};
+use std::sync::OnceLock;
 use std::{fs, io, path::PathBuf};
 
+/// Resolved once per process, from the global `--root` flag or the default,
+/// so the forked supervisor keeps using it even after it has become root
+/// inside a user namespace.
+static ROOT: OnceLock<PathBuf> = OnceLock::new();
+
 pub struct Store {
     root: PathBuf,
 }
 
 impl Store {
     pub fn new() -> Result<Self> {
-        let home = std::env::var_os("HOME")
-            .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "HOME not set"))?;
-        Ok(Self {
-            root: PathBuf::from(home).join(".podrun").join("containers"),
-        })
+        if let Some(root) = ROOT.get() {
+            return Ok(Self { root: root.clone() });
+        }
+
+        let root = default_root()?;
+        let _ = ROOT.set(root.clone());
+        Ok(Self { root })
+    }
+
+    pub fn set_root(root: PathBuf) -> Result<()> {
+        // absolute, since the supervisor outlives the caller's cwd
+        let root = std::path::absolute(root)?;
+        ROOT.set(root)
+            .map_err(|_| io::Error::other("store root already set").into())
     }
 
     pub fn exists(&self, id: &str) -> bool {
-        self.root.join(id).exists()
+        self.dir(id).exists()
     }
 
     pub fn create_container(&self, id: &str, spec: &Spec, state: &State) -> Result<()> {
-        fs::create_dir_all(self.root.join(id))?;
-        write_json(self.root.join(id).join("spec.json"), spec)?;
-        write_json(self.root.join(id).join("state.json"), state)?;
+        fs::create_dir_all(self.dir(id))?;
+        write_json(self.dir(id).join("spec.json"), spec)?;
+        write_json(self.dir(id).join("state.json"), state)?;
         Ok(())
     }
 
     pub fn load_spec(&self, id: &str) -> Result<Spec> {
-        read_json(self.root.join(id).join("spec.json"))
+        read_json(self.dir(id).join("spec.json"))
     }
 
     pub fn load_state(&self, id: &str) -> Result<State> {
-        read_json(self.root.join(id).join("state.json"))
+        read_json(self.dir(id).join("state.json"))
     }
 
-    pub fn save_state(&self, id: &str, state: &State) -> Result<()> {
-        write_json(self.root.join(id).join("state.json"), state)
+    fn save_state(&self, id: &str, state: &State) -> Result<()> {
+        write_json(self.dir(id).join("state.json"), state)
+    }
+
+    /// Loads the state, lets `f` change it and saves it, all under the
+    /// container's lock, so the CLI and the supervisor can't lose each
+    /// other's writes. Nothing is saved if `f` fails.
+    pub fn update_state(
+        &self,
+        id: &str,
+        f: impl FnOnce(&mut State) -> Result<()>,
+    ) -> Result<State> {
+        let _lock = self.lock_state(id)?;
+        let mut state = self.load_state(id)?;
+        f(&mut state)?;
+        self.save_state(id, &state)?;
+        Ok(state)
+    }
+
+    fn lock_state(&self, id: &str) -> Result<Flock<File>> {
+        let lock = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(self.dir(id).join("state.lock"))?;
+        Flock::lock(lock, FlockArg::LockExclusive).map_err(|(_, e)| nix_to_io(e).into())
     }
 
     pub fn dir(&self, id: &str) -> PathBuf {
-        self.root.join(id)
+        self.containers_dir().join(id)
+    }
+
+    pub fn network_dir(&self) -> PathBuf {
+        self.root.join("network")
+    }
+
+    pub fn volumes_dir(&self) -> PathBuf {
+        self.root.join("volumes")
+    }
+
+    pub fn volume_dir(&self, name: &str) -> PathBuf {
+        self.volumes_dir().join(name)
+    }
+
+    fn containers_dir(&self) -> PathBuf {
+        self.root.join("containers")
     }
 
     pub fn list_ids(&self) -> crate::runtime::Result<Vec<String>> {
-        let root = self.root.clone();
+        let root = self.containers_dir();
 
         if !root.exists() {
             return Ok(vec![]);
@@ -61,13 +120,30 @@ impl Store {
     }
 }
 
-fn write_json<T: serde::Serialize>(path: PathBuf, v: &T) -> Result<()> {
-    let s = serde_json::to_string_pretty(v).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
-    fs::write(path, s)?;
+/// Rootless state is per login session, as runc and podman keep it.
+fn default_root() -> Result<PathBuf> {
+    if !Uid::effective().is_root()
+        && let Some(dir) = std::env::var_os("XDG_RUNTIME_DIR").filter(|d| !d.is_empty())
+    {
+        return Ok(PathBuf::from(dir).join("podrun"));
+    }
+
+    let home = std::env::var_os("HOME")
+        .ok_or_else(|| io::Error::other("HOME not set"))?;
+    Ok(PathBuf::from(home).join(".podrun"))
+}
+
+pub(crate) fn write_json<T: serde::Serialize>(path: PathBuf, v: &T) -> Result<()> {
+    let s = serde_json::to_string_pretty(v).map_err(io::Error::other)?;
+    // write-then-rename so a concurrent reader (e.g. the supervisor racing a
+    // `kill`) never sees a truncated file
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, s)?;
+    fs::rename(&tmp, &path)?;
     Ok(())
 }
 
-fn read_json<T: serde::de::DeserializeOwned>(path: PathBuf) -> Result<T> {
+pub(crate) fn read_json<T: serde::de::DeserializeOwned>(path: PathBuf) -> Result<T> {
     let s = fs::read_to_string(path)?;
-    serde_json::from_str(&s).map_err(|e| io::Error::new(io::ErrorKind::Other, e).into())
+    serde_json::from_str(&s).map_err(|e| io::Error::other(e).into())
 }
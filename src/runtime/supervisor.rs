@@ -0,0 +1,251 @@
+use super::unix::now_unix;
+use crate::linux::exec_fifo;
+use crate::linux::isolation::{enter_user_namespace, nix_to_io};
+use crate::linux::seccomp_learn::Learner;
+use crate::linux::userns;
+use crate::runtime::spec::IdMapping;
+use crate::runtime::state::Status;
+use crate::runtime::{Result, RuntimeError, store::Store};
+use nix::errno::Errno;
+use nix::fcntl::OFlag;
+use nix::sys::prctl;
+use nix::sys::wait::{WaitStatus, waitpid};
+use nix::unistd::{ForkResult, Pid, fork, getpid, pipe2, setsid};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::mem::ManuallyDrop;
+use std::os::fd::{AsRawFd, FromRawFd};
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::Command;
+
+/// Forks a long-lived supervisor that forks the container init from `cmd`,
+/// stays its parent (and subreaper for anything it leaves behind), and records
+/// the exit status in state.json once init is reaped.
+///
+/// `prepare` runs in the supervisor right before init is forked, for setup
+/// that only takes effect for children (a new PID namespace) or that init
+/// should find already in place (its network namespace).
+///
+/// With `id_mappings` (uids, gids), the supervisor first moves into a new
+/// user namespace, which this process maps from outside; the PID namespace
+/// and everything init creates are then owned by it.
+///
+/// Init runs all of `cmd`'s setup and then blocks on `exec_fifo` until
+/// `start`. Returns the host pid of init once it got that far.
+///
+/// With `seccomp_learn`, init is expected to install the learning filter;
+/// a helper of the supervisor serves it and writes the profile there once
+/// init is reaped.
+pub fn spawn<F>(
+    id: &str,
+    cmd: Command,
+    exec_fifo: File,
+    id_mappings: Option<(&[IdMapping], &[IdMapping])>,
+    seccomp_learn: Option<&Path>,
+    prepare: F,
+) -> Result<i32>
+where
+    F: FnOnce() -> Result<()>,
+{
+    // CLOEXEC so init never inherits the write end and holds the pipe open.
+    let (rx, tx) = pipe2(OFlag::O_CLOEXEC).map_err(nix_to_io)?;
+    let (mapped_rx, mapped_tx) = pipe2(OFlag::O_CLOEXEC).map_err(nix_to_io)?;
+
+    match unsafe { fork() }.map_err(nix_to_io)? {
+        ForkResult::Child => {
+            drop(rx);
+            drop(mapped_tx);
+            let user_namespace = id_mappings.map(|_| File::from(mapped_rx));
+            let code = match supervise(
+                id,
+                cmd,
+                exec_fifo,
+                user_namespace,
+                seccomp_learn,
+                prepare,
+                File::from(tx),
+            ) {
+                Ok(()) => 0,
+                Err(_) => 1,
+            };
+            std::process::exit(code);
+        }
+        ForkResult::Parent { child } => {
+            drop(tx);
+            drop(mapped_rx);
+            // lines, not EOF: init keeps the inherited end until it execs
+            let mut reader = BufReader::new(File::from(rx));
+            let mut msg = String::new();
+            reader.read_line(&mut msg)?;
+
+            if msg.trim_end() == "map" {
+                let (uids, gids) = id_mappings.unwrap_or_default();
+                // on error the supervisor sees EOF instead and gives up
+                userns::write_mappings(child.as_raw(), uids, gids)?;
+                File::from(mapped_tx).write_all(b"1")?;
+
+                msg.clear();
+                reader.read_line(&mut msg)?;
+            }
+
+            parse_report(msg.trim_end())
+        }
+    }
+}
+
+fn parse_report(msg: &str) -> Result<i32> {
+    if let Some(pid) = msg.strip_prefix("pid:") {
+        return pid
+            .parse()
+            .map_err(|_| RuntimeError::Msg(format!("supervisor sent invalid pid: {pid}")));
+    }
+
+    if let Some(err) = msg.strip_prefix("error:") {
+        return Err(RuntimeError::Msg(err.to_string()));
+    }
+
+    Err(RuntimeError::Msg(
+        "supervisor exited before starting init".into(),
+    ))
+}
+
+fn supervise<F>(
+    id: &str,
+    cmd: Command,
+    exec_fifo: File,
+    user_namespace: Option<File>,
+    seccomp_learn: Option<&Path>,
+    prepare: F,
+    mut report: File,
+) -> Result<()>
+where
+    F: FnOnce() -> Result<()>,
+{
+    let created = (|| -> Result<(i32, Option<Learner>)> {
+        // detach from the caller's session so `podrun create` can exit freely
+        setsid().map_err(nix_to_io)?;
+        prctl::set_child_subreaper(true).map_err(nix_to_io)?;
+
+        if let Some(mut mapped) = user_namespace {
+            enter_user_namespace()?;
+            writeln!(report, "map")?;
+            let mut buf = [0u8; 1];
+            if mapped.read(&mut buf)? != 1 {
+                return Err(RuntimeError::Msg("user namespace was not mapped".into()));
+            }
+        }
+
+        // outside the PID namespace `prepare` unshares
+        let learner = seccomp_learn.map(Learner::start).transpose()?;
+
+        prepare()?;
+
+        let pid = fork_init(cmd, exec_fifo, learner.as_ref())?;
+
+        Store::new()?.update_state(id, |state| {
+            state.pid = Some(pid);
+            state.supervisor_pid = Some(getpid().as_raw());
+            Ok(())
+        })?;
+
+        Ok((pid, learner))
+    })();
+
+    let (pid, learner) = match created {
+        Ok(created) => {
+            let pid = created.0;
+            let _ = writeln!(report, "pid:{pid}");
+            created
+        }
+        Err(e) => {
+            let _ = writeln!(report, "error:{e}");
+            return Err(e);
+        }
+    };
+    drop(report);
+
+    let (exit_code, exit_signal) = reap(Pid::from_raw(pid))?;
+
+    // before Stopped, so whoever waits on the container finds it written
+    if let Some(learner) = learner {
+        learner.finish();
+    }
+
+    Store::new()?.update_state(id, |state| {
+        state.status = Status::Stopped;
+        state.pid = None;
+        state.supervisor_pid = None;
+        state.exit_code = Some(exit_code);
+        state.exit_signal = exit_signal;
+        state.finished_at_unix = Some(now_unix());
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+/// Forks init and waits until it has finished its setup and is parked on the
+/// exec FIFO, or has failed trying. `Command::spawn` can't be used: it only
+/// returns once the child has exec'd, i.e. after `start`.
+fn fork_init(mut cmd: Command, exec_fifo: File, learner: Option<&Learner>) -> Result<i32> {
+    let (rx, tx) = pipe2(OFlag::O_CLOEXEC).map_err(nix_to_io)?;
+    let mut ready = File::from(tx);
+    let ready_fd = ready.as_raw_fd();
+
+    // runs after the container's own pre_exec setup
+    unsafe {
+        cmd.pre_exec(move || {
+            let mut ready = ManuallyDrop::new(File::from_raw_fd(ready_fd));
+            ready.write_all(b"ready\n")?;
+            exec_fifo::wait(&exec_fifo)
+        });
+    }
+
+    match unsafe { fork() }.map_err(nix_to_io)? {
+        ForkResult::Child => {
+            drop(rx);
+            // only returns on failure
+            let err = cmd.exec();
+            let _ = writeln!(ready, "error:{err}");
+            unsafe { libc::_exit(127) }
+        }
+        ForkResult::Parent { child } => {
+            // the pty slave and other stdio belong to init alone now
+            drop(cmd);
+            drop(ready);
+            if let Some(learner) = learner {
+                learner.watch(child.as_raw());
+            }
+
+            let mut msg = String::new();
+            BufReader::new(File::from(rx)).read_line(&mut msg)?;
+            let msg = msg.trim_end();
+
+            if msg == "ready" {
+                return Ok(child.as_raw());
+            }
+
+            let _ = waitpid(child, None);
+            Err(RuntimeError::Msg(match msg.strip_prefix("error:") {
+                Some(err) => format!("container init: {err}"),
+                None => "container init exited during setup".into(),
+            }))
+        }
+    }
+}
+
+/// Reaps every child until init itself exits. Signal deaths are reported the
+/// way shells do, as 128 + signal number.
+fn reap(init: Pid) -> Result<(i32, Option<i32>)> {
+    loop {
+        match waitpid(None, None) {
+            Ok(WaitStatus::Exited(pid, code)) if pid == init => return Ok((code, None)),
+            Ok(WaitStatus::Signaled(pid, sig, _)) if pid == init => {
+                return Ok((128 + sig as i32, Some(sig as i32)));
+            }
+            Ok(_) | Err(Errno::EINTR) => continue,
+            Err(e) => return Err(RuntimeError::Io(nix_to_io(e))),
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use crate::linux::isolation::nix_to_io;
+use crate::runtime::store::{Store, read_json, write_json};
+use crate::runtime::unix::now_unix;
+use crate::runtime::{Result, RuntimeError};
+use nix::fcntl::{Flock, FlockArg};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs::{self, File, OpenOptions};
+use std::path::PathBuf;
+
+/// A named directory under the store that outlives the containers mounting
+/// it, recorded in `volumes/<name>/volume.json` next to its `_data`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Volume {
+    pub name: String,
+    pub mountpoint: PathBuf,
+    pub created_at_unix: u64,
+    /// Containers that mount this volume; `rm` refuses while any remain
+    #[serde(default)]
+    pub containers: BTreeSet<String>,
+}
+
+pub fn create(store: &Store, name: &str) -> Result<Volume> {
+    validate_name(name)?;
+    let _lock = lock_volumes(store)?;
+    if store.volume_dir(name).exists() {
+        return Err(RuntimeError::Msg(format!("volume {name} already exists")));
+    }
+    create_locked(store, name)
+}
+
+pub fn inspect(store: &Store, name: &str) -> Result<Volume> {
+    validate_name(name)?;
+    let _lock = lock_volumes(store)?;
+    load(store, name)
+}
+
+pub fn list(store: &Store) -> Result<Vec<Volume>> {
+    let _lock = lock_volumes(store)?;
+    let dir = store.volumes_dir();
+
+    let mut volumes = Vec::new();
+    for entry in fs::read_dir(&dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            volumes.push(load(store, &entry.file_name().to_string_lossy())?);
+        }
+    }
+    volumes.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(volumes)
+}
+
+pub fn remove(store: &Store, name: &str) -> Result<()> {
+    validate_name(name)?;
+    let _lock = lock_volumes(store)?;
+    let vol = load(store, name)?;
+
+    let users = live_users(store, &vol);
+    if !users.is_empty() {
+        return Err(RuntimeError::Msg(format!(
+            "volume {name} is in use by {}",
+            users.into_iter().collect::<Vec<_>>().join(", ")
+        )));
+    }
+    fs::remove_dir_all(store.volume_dir(name))?;
+    Ok(())
+}
+
+/// Removes every volume no container uses and returns their names.
+pub fn prune(store: &Store) -> Result<Vec<String>> {
+    let mut pruned = Vec::new();
+    for vol in list(store)? {
+        let _lock = lock_volumes(store)?;
+        // re-read under the lock, a create may have taken it in between
+        let vol = load(store, &vol.name)?;
+        if live_users(store, &vol).is_empty() {
+            fs::remove_dir_all(store.volume_dir(&vol.name))?;
+            pruned.push(vol.name);
+        }
+    }
+    Ok(pruned)
+}
+
+/// Records `id` as a user of `name`, creating the volume on first use.
+pub fn acquire(store: &Store, name: &str, id: &str) -> Result<()> {
+    let _lock = lock_volumes(store)?;
+    let mut vol = if store.volume_dir(name).exists() {
+        load(store, name)?
+    } else {
+        create_locked(store, name)?
+    };
+
+    if vol.containers.insert(id.to_string()) {
+        save(store, &vol)?;
+    }
+    Ok(())
+}
+
+pub fn release(store: &Store, name: &str, id: &str) -> Result<()> {
+    let _lock = lock_volumes(store)?;
+    if !store.volume_dir(name).exists() {
+        return Ok(());
+    }
+
+    let mut vol = load(store, name)?;
+    if vol.containers.remove(id) {
+        save(store, &vol)?;
+    }
+    Ok(())
+}
+
+/// Where a volume's contents live on the host, i.e. what gets bind-mounted.
+pub fn mountpoint(store: &Store, name: &str) -> Result<PathBuf> {
+    validate_name(name)?;
+    Ok(store.volume_dir(name).join("_data"))
+}
+
+/// Names end up as directory names, so no slashes, dots or empty names.
+fn validate_name(name: &str) -> Result<()> {
+    let mut chars = name.chars();
+    let valid = chars.next().is_some_and(|c| c.is_ascii_alphanumeric())
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'));
+    if !valid {
+        return Err(RuntimeError::Msg(format!(
+            "invalid volume name '{name}': use letters, digits, '_', '.' and '-'"
+        )));
+    }
+    Ok(())
+}
+
+fn create_locked(store: &Store, name: &str) -> Result<Volume> {
+    let vol = Volume {
+        name: name.to_string(),
+        mountpoint: mountpoint(store, name)?,
+        created_at_unix: now_unix(),
+        containers: BTreeSet::new(),
+    };
+    fs::create_dir_all(&vol.mountpoint)?;
+    save(store, &vol)?;
+    Ok(vol)
+}
+
+fn load(store: &Store, name: &str) -> Result<Volume> {
+    let dir = store.volume_dir(name);
+    if !dir.exists() {
+        return Err(RuntimeError::Msg(format!("volume {name} not found")));
+    }
+    read_json(dir.join("volume.json"))
+}
+
+fn save(store: &Store, vol: &Volume) -> Result<()> {
+    write_json(store.volume_dir(&vol.name).join("volume.json"), vol)
+}
+
+/// Users that still exist; a container whose directory was removed behind
+/// our back must not pin the volume forever.
+fn live_users(store: &Store, vol: &Volume) -> BTreeSet<String> {
+    vol.containers
+        .iter()
+        .filter(|id| store.exists(id))
+        .cloned()
+        .collect()
+}
+
+fn lock_volumes(store: &Store) -> Result<Flock<File>> {
+    let dir = store.volumes_dir();
+    fs::create_dir_all(&dir)?;
+
+    let lock = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(dir.join("volumes.lock"))?;
+    Flock::lock(lock, FlockArg::LockExclusive).map_err(|(_, e)| nix_to_io(e).into())
+}